@@ -2,88 +2,124 @@
 //! aarch: <https://github.com/ARM-software/abi-aa/releases/download/2023Q3/aaelf64.pdf>
 
 extern crate alloc;
-use core::mem::size_of;
-
-use super::RelocatePair;
+use super::{dyn_symbol, NoResolver, RelocContext, RelocTable, RelocatePair, SymbolResolver};
+use crate::{
+    note::{GNU_PROPERTY_AARCH64_FEATURE_1_BTI, GNU_PROPERTY_AARCH64_FEATURE_1_PAC},
+    ELFSegment, ElfClass, ElfError, ElfResult, GnuProperties,
+};
 use alloc::vec::Vec;
 use log::info;
 use memory_addr::VirtAddr;
-use xmas_elf::symbol_table::Entry;
+use page_table_entry::MappingFlags;
 
 pub const R_AARCH32_GLOBAL_DATA: u32 = 181;
 pub const R_AARCH64_GLOBAL_DATA: u32 = 1025;
 pub const R_AARCH64_JUMP_SLOT: u32 = 1026;
 pub const R_AARCH64_RELATIVE: u32 = 1027;
 
-/// Read relocate pairs from the elf file.
+/// Branch protection requested by the GNU properties of an aarch64 image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchProtection {
+    /// The image is compatible with Branch Target Identification, so its executable pages
+    /// can be guarded
+    pub bti: bool,
+    /// The image is compatible with Pointer Authentication
+    pub pac: bool,
+}
+
+impl BranchProtection {
+    /// Read the branch protection from the properties of an aarch64 image.
+    pub fn from_properties(properties: &GnuProperties) -> Self {
+        Self {
+            bti: properties.aarch64_features & GNU_PROPERTY_AARCH64_FEATURE_1_BTI != 0,
+            pac: properties.aarch64_features & GNU_PROPERTY_AARCH64_FEATURE_1_PAC != 0,
+        }
+    }
+}
+
+/// Mark the executable segments as guarded if the image is compatible with BTI.
+///
+/// When a dynamic linker is loaded, Linux applies BTI only to the segments of the
+/// interpreter, which then protects the main program and the libraries by itself.
+/// [`crate::ExecPlan`] follows the same rule.
+pub fn guard_segments(segments: &mut [ELFSegment], protection: BranchProtection) {
+    if !protection.bti {
+        return;
+    }
+    for segment in segments
+        .iter_mut()
+        .filter(|segment| segment.flags.contains(MappingFlags::EXECUTE))
+    {
+        segment.guarded = true;
+    }
+}
+
+/// Read relocate pairs from the elf file, without resolving the undefined symbols.
+///
+/// See [`relocate_pairs_with`].
+pub fn relocate_pairs(elf: &xmas_elf::ElfFile, base_addr: usize) -> ElfResult<Vec<RelocatePair>> {
+    relocate_pairs_with(elf, base_addr, &NoResolver)
+}
+
+/// Read relocate pairs from the elf file, looking up the undefined symbols with `resolver`.
 ///
 /// # Arguments
 ///
 /// * `elf` - The [`xmas_elf::ElfFile`] data
 /// * `base_addr` - The base address of the elf file if the file will be loaded to the memory
+/// * `resolver` - The [`SymbolResolver`] of the symbols undefined in the elf file
 ///
 /// # Return
 /// A vector of [`super::RelocatePair`] which contains the source
 /// and destination address of the relocation.
-pub fn relocate_pairs(elf: &xmas_elf::ElfFile, base_addr: usize) -> Vec<RelocatePair> {
-    let elf_header = elf.header;
-    let magic = elf_header.pt1.magic;
-    assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-    let mut pairs = Vec::new();
-    info!("Base addr for the elf: 0x{:x}", base_addr);
-    if let Some(rela_dyn) = elf.find_section_by_name(".rela.dyn") {
-        let data = match rela_dyn.get_data(elf) {
-            Ok(xmas_elf::sections::SectionData::Rela64(data)) => data,
-            _ => panic!("Invalid data in .rela.dyn section"),
-        };
-
-        if let Some(dyn_sym_table) = elf.find_section_by_name(".dynsym") {
-            let dyn_sym_table = match dyn_sym_table.get_data(elf) {
-                Ok(xmas_elf::sections::SectionData::DynSymbolTable64(dyn_sym_table)) => {
-                    dyn_sym_table
-                }
-                _ => panic!("Invalid data in .dynsym section"),
-            };
+pub fn relocate_pairs_with(
+    elf: &xmas_elf::ElfFile,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    relocate(&RelocContext::new(elf)?, base_addr, resolver)
+}
 
+/// Read relocate pairs from the tables read by `context`. See [`relocate_pairs_with`].
+pub(crate) fn relocate(
+    context: &RelocContext,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    let word = ElfClass::from_elf(context.elf)?.word_size();
+    let mut pairs = context.relr_pairs(base_addr)?;
+    info!("Base addr for the elf: 0x{:x}", base_addr);
+    if let Some(data) = context.reloc_entries(RelocTable::Dyn)? {
+        if let Some(dyn_sym_table) = &context.symbols {
             info!("Relocating .rela.dyn");
-            for entry in data {
-                let dyn_sym = &dyn_sym_table[entry.get_symbol_table_index() as usize];
-                let destination = base_addr + entry.get_offset() as usize;
+            for entry in &data {
+                let dyn_sym = dyn_symbol(dyn_sym_table, entry.sym)?;
+                let destination = base_addr.wrapping_add(entry.offset);
                 // S: (when used on its own) is the address of the symbol.
                 // Warn: in riscv and x86, it stands for the value, why in arm it stand for the address?
-                let symbol_value = dyn_sym.value() as usize; // Represents the value of the symbol whose index resides in the relocation entry.
-                let addend = entry.get_addend() as usize; // Represents the addend used to compute the value of the relocatable field.
+                let addend = entry.addend; // Represents the addend used to compute the value of the relocatable field.
 
-                match entry.get_type() {
+                match entry.ty {
                     R_AARCH32_GLOBAL_DATA => {
-                        if dyn_sym.shndx() == 0 {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(symbol_value + addend),
+                            src: VirtAddr::from(symbol_value.wrapping_add(addend)),
                             dst: VirtAddr::from(destination),
                             count: 4,
                         })
                     }
                     R_AARCH64_GLOBAL_DATA => {
-                        if dyn_sym.shndx() == 0 {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(symbol_value + addend),
+                            src: VirtAddr::from(symbol_value.wrapping_add(addend)),
                             dst: VirtAddr::from(destination),
                             count: 8,
                         })
                     }
                     R_AARCH64_JUMP_SLOT => {
-                        if dyn_sym.shndx() == 0 {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(symbol_value + addend),
+                            src: VirtAddr::from(symbol_value.wrapping_add(addend)),
                             dst: VirtAddr::from(destination),
                             count: 8,
                         })
@@ -92,59 +128,39 @@ pub fn relocate_pairs(elf: &xmas_elf::ElfFile, base_addr: usize) -> Vec<Relocate
                         // Delta (S) if s is a normal symbol, resolves to the difference between the static link address of s and theexecution address of s.
                         // If s is the null symbol (ELF symbol index 0), resolves to the diference between the staticlink address of p and the execution address of P.
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(base_addr + addend),
+                            src: VirtAddr::from(base_addr.wrapping_add(addend)),
                             dst: VirtAddr::from(destination),
                             count: 8,
                         })
                     }
 
-                    other => panic!("Unknown relocation type: {}", other),
+                    other => return Err(ElfError::UnsupportedRelocation(other)),
                 }
             }
         }
     }
 
     // Relocate .rela.plt sections
-    if let Some(rela_plt) = elf.find_section_by_name(".rela.plt") {
-        let data = match rela_plt.get_data(elf) {
-            Ok(xmas_elf::sections::SectionData::Rela64(data)) => data,
-            _ => panic!("Invalid data in .rela.plt section"),
-        };
-        if elf.find_section_by_name(".dynsym").is_some() {
-            let dyn_sym_table = match elf
-                .find_section_by_name(".dynsym")
-                .expect("Dynamic Symbol Table not found for .rela.plt section")
-                .get_data(elf)
-            {
-                Ok(xmas_elf::sections::SectionData::DynSymbolTable64(dyn_sym_table)) => {
-                    dyn_sym_table
-                }
-                _ => panic!("Invalid data in .dynsym section"),
-            };
-
+    if let Some(data) = context.reloc_entries(RelocTable::Plt)? {
+        if let Some(dyn_sym_table) = &context.symbols {
             info!("Relocating .rela.plt");
-            for entry in data {
-                let dyn_sym = &dyn_sym_table[entry.get_symbol_table_index() as usize];
-                let destination = base_addr + entry.get_offset() as usize;
-                match entry.get_type() {
+            for entry in &data {
+                let dyn_sym = dyn_symbol(dyn_sym_table, entry.sym)?;
+                let destination = base_addr.wrapping_add(entry.offset);
+                match entry.ty {
                     R_AARCH64_JUMP_SLOT => {
-                        let symbol_value = if dyn_sym.shndx() != 0 {
-                            dyn_sym.value() as usize
-                        } else {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }; // Represents the value of the symbol whose index resides in the relocation entry.
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?; // Represents the value of the symbol whose index resides in the relocation entry.
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(symbol_value + base_addr),
+                            src: VirtAddr::from(symbol_value),
                             dst: VirtAddr::from(destination),
-                            count: size_of::<usize>(),
+                            count: word,
                         });
                     }
-                    other => panic!("Unknown relocation type: {}", other),
+                    other => return Err(ElfError::UnsupportedRelocation(other)),
                 }
             }
         }
     }
     info!("Relocating done");
-    pairs
+    Ok(pairs)
 }
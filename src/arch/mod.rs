@@ -1,6 +1,32 @@
 //! Architecture-specific types and operations about relocation for ELF file.
+#![cfg_attr(
+    not(any(
+        feature = "x86_64",
+        feature = "x86",
+        feature = "riscv",
+        feature = "aarch64",
+        feature = "arm"
+    )),
+    allow(dead_code, unused_variables)
+)]
 
+extern crate alloc;
+use alloc::{borrow::Cow, vec::Vec};
+use core::ops::Range;
 use memory_addr::VirtAddr;
+use xmas_elf::{
+    header::{self, Data},
+    program::Type,
+    ElfFile,
+};
+
+use crate::{
+    dynamic::{dynamic_info, read_uint, vaddr_range, vaddr_tail, DynamicInfo, RelocFormat},
+    image::program_headers,
+    section::{self, find_section},
+    ElfClass, ElfError, ElfResult, ProgramHeaderInfo,
+};
+
 #[derive(Debug)]
 /// To describe the relocation pair in the ELF file
 pub struct RelocatePair {
@@ -12,15 +38,673 @@ pub struct RelocatePair {
     pub count: usize,
 }
 
-cfg_if::cfg_if! {
-    if #[cfg(target_arch = "x86_64")] {
-        mod x86_64;
-        pub use x86_64::*;
-    } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
-        mod riscv;
-        pub use riscv::*;
-    } else if #[cfg(target_arch = "aarch64")]{
-        mod aarch64;
-        pub use self::aarch64::*;
+#[cfg(feature = "aarch64")]
+pub mod aarch64;
+#[cfg(feature = "arm")]
+pub mod arm;
+#[cfg(feature = "riscv")]
+pub mod riscv;
+#[cfg(feature = "x86")]
+pub mod x86;
+#[cfg(feature = "x86_64")]
+pub mod x86_64;
+
+/// The architectures whose relocations can be computed by this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Machine {
+    /// x86_64 (`EM_X86_64`)
+    X86_64,
+    /// i386 (`EM_386`)
+    X86,
+    /// riscv32 and riscv64 (`EM_RISCV`)
+    RiscV,
+    /// aarch64 (`EM_AARCH64`)
+    AArch64,
+    /// 32-bit arm (`EM_ARM`)
+    Arm,
+}
+
+impl Machine {
+    /// The machine of the running architecture, if it is supported.
+    pub const fn host() -> Option<Self> {
+        if cfg!(target_arch = "x86_64") {
+            Some(Self::X86_64)
+        } else if cfg!(target_arch = "x86") {
+            Some(Self::X86)
+        } else if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+            Some(Self::RiscV)
+        } else if cfg!(target_arch = "aarch64") {
+            Some(Self::AArch64)
+        } else if cfg!(target_arch = "arm") {
+            Some(Self::Arm)
+        } else {
+            None
+        }
+    }
+
+    /// Read the machine from `e_machine` of the elf file.
+    pub fn from_elf(elf: &ElfFile) -> ElfResult<Self> {
+        match elf.header.pt2.machine().as_machine() {
+            header::Machine::X86_64 => Ok(Self::X86_64),
+            header::Machine::X86 => Ok(Self::X86),
+            header::Machine::RISC_V => Ok(Self::RiscV),
+            header::Machine::AArch64 => Ok(Self::AArch64),
+            header::Machine::Arm => Ok(Self::Arm),
+            _ => Err(ElfError::BadMachine(e_machine(elf))),
+        }
+    }
+
+    /// The `e_machine` value of the machine.
+    pub const fn e_machine(self) -> u16 {
+        match self {
+            Self::X86_64 => 0x3e,
+            Self::X86 => 0x03,
+            Self::RiscV => 0xf3,
+            Self::AArch64 => 0xb7,
+            Self::Arm => 0x28,
+        }
+    }
+
+    /// Whether the relocation back-end of the machine is enabled by the cargo features.
+    pub const fn is_enabled(self) -> bool {
+        match self {
+            Self::X86_64 => cfg!(feature = "x86_64"),
+            Self::X86 => cfg!(feature = "x86"),
+            Self::RiscV => cfg!(feature = "riscv"),
+            Self::AArch64 => cfg!(feature = "aarch64"),
+            Self::Arm => cfg!(feature = "arm"),
+        }
+    }
+}
+
+/// The raw `e_machine` value of the elf file.
+pub(crate) fn e_machine(elf: &ElfFile) -> u16 {
+    let input = elf.input;
+    u16::from_le_bytes([input[18], input[19]])
+}
+
+/// Resolves the symbols which are undefined in the ELF file, e.g. those provided by the vDSO
+/// or by a preloaded library.
+pub trait SymbolResolver {
+    /// The runtime address of the symbol named `name`, or `None` if it is not found.
+    fn resolve(&self, name: &str) -> Option<usize>;
+}
+
+impl<F: Fn(&str) -> Option<usize>> SymbolResolver for F {
+    fn resolve(&self, name: &str) -> Option<usize> {
+        self(name)
+    }
+}
+
+/// A [`SymbolResolver`] which finds no symbol, so only the weak undefined symbols can be
+/// relocated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoResolver;
+
+impl SymbolResolver for NoResolver {
+    fn resolve(&self, _name: &str) -> Option<usize> {
+        None
+    }
+}
+
+/// Read relocate pairs from the elf file with the back-end chosen by its `e_machine`.
+///
+/// The undefined symbols are not resolved. See [`relocate_pairs_with`].
+///
+/// # Arguments
+///
+/// * `elf` - The [`xmas_elf::ElfFile`] data
+/// * `base_addr` - The base address of the elf file if the file will be loaded to the memory
+///
+/// # Return
+/// A vector of [`RelocatePair`] which contains the source
+/// and destination address of the relocation.
+pub fn relocate_pairs(elf: &ElfFile, base_addr: usize) -> ElfResult<Vec<RelocatePair>> {
+    relocate_pairs_with(elf, base_addr, &NoResolver)
+}
+
+/// Read relocate pairs from the elf file with the back-end chosen by its `e_machine`, looking
+/// up the undefined symbols with `resolver`.
+///
+/// The weak undefined symbols which `resolver` does not find are resolved to 0, and any other
+/// symbol it does not find fails with [`ElfError::SymbolNotFound`].
+pub fn relocate_pairs_with(
+    elf: &ElfFile,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    let context = RelocContext::new(elf)?;
+    dispatch(Machine::from_elf(elf)?, &context, base_addr, resolver)
+}
+
+/// Read relocate pairs from the elf file with the back-end of `machine`.
+///
+/// It fails with [`ElfError::BadMachine`] if `machine` does not match the `e_machine` of the
+/// file, or if its back-end is disabled.
+pub fn relocate_pairs_for(
+    machine: Machine,
+    elf: &ElfFile,
+    base_addr: usize,
+) -> ElfResult<Vec<RelocatePair>> {
+    dispatch(machine, &RelocContext::new(elf)?, base_addr, &NoResolver)
+}
+
+/// Read relocate pairs with the back-end of `machine` from the tables read by `context`.
+pub(crate) fn dispatch(
+    machine: Machine,
+    context: &RelocContext,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    let elf = context.elf;
+    if e_machine(elf) != machine.e_machine() {
+        return Err(ElfError::BadMachine(e_machine(elf)));
+    }
+    match machine {
+        #[cfg(feature = "x86_64")]
+        Machine::X86_64 => x86_64::relocate(context, base_addr, resolver),
+        #[cfg(feature = "x86")]
+        Machine::X86 => x86::relocate(context, base_addr, resolver),
+        #[cfg(feature = "riscv")]
+        Machine::RiscV => riscv::relocate(context, base_addr, resolver),
+        #[cfg(feature = "aarch64")]
+        Machine::AArch64 => aarch64::relocate(context, base_addr, resolver),
+        #[cfg(feature = "arm")]
+        Machine::Arm => arm::relocate(context, base_addr, resolver),
+        #[allow(unreachable_patterns)]
+        _ => Err(ElfError::BadMachine(machine.e_machine())),
+    }
+}
+
+/// Binding of a weak symbol, which may be left undefined.
+const STB_WEAK: u8 = 2;
+/// Section index of an undefined symbol.
+const SHN_UNDEF: u16 = 0;
+/// Section index of an absolute symbol, whose value is not relative to the base.
+const SHN_ABS: u16 = 0xfff1;
+
+/// The memory of a loaded ELF image, which the relocations are written to.
+///
+/// It may be backed by the user page tables, the buffers of the segments or a host buffer.
+pub trait RelocTarget {
+    /// Write `bytes` at the runtime address `addr`.
+    fn write(&mut self, addr: VirtAddr, bytes: &[u8]) -> ElfResult;
+}
+
+/// Apply the relocations of the elf file loaded at `base_addr` to `target`.
+///
+/// The undefined symbols are not resolved. See [`apply_relocations_with`].
+pub fn apply_relocations(
+    elf: &ElfFile,
+    base_addr: usize,
+    target: &mut impl RelocTarget,
+) -> ElfResult {
+    apply_relocations_with(elf, base_addr, &NoResolver, target)
+}
+
+/// Apply the relocations of the elf file loaded at `base_addr` to `target`, looking up the
+/// undefined symbols with `resolver`.
+///
+/// The values are written in little-endian, the only byte order whose tables are read, and
+/// other files fail with [`ElfError::BadEncoding`]. Every relocated field must be in a
+/// writable `LOAD` segment, otherwise it fails with [`ElfError::BadSegment`] before anything
+/// is written.
+pub fn apply_relocations_with(
+    elf: &ElfFile,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+    target: &mut impl RelocTarget,
+) -> ElfResult {
+    let context = RelocContext::new(elf)?;
+    apply_from(
+        Machine::from_elf(elf)?,
+        &context,
+        base_addr,
+        resolver,
+        target,
+    )
+}
+
+/// Apply the relocations with the back-end of `machine` from the tables read by `context`.
+pub(crate) fn apply_from(
+    machine: Machine,
+    context: &RelocContext,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+    target: &mut impl RelocTarget,
+) -> ElfResult {
+    if context.elf.header.pt1.data() != Data::LittleEndian {
+        return Err(ElfError::BadEncoding);
+    }
+    let writable: Vec<Range<usize>> = context
+        .program_headers
+        .iter()
+        .filter(|ph| ph.type_ == Type::Load && ph.flags.is_write())
+        .map(|ph| {
+            let start = base_addr.wrapping_add(ph.vaddr as usize);
+            start..start.wrapping_add(ph.mem_size as usize)
+        })
+        .collect();
+    let pairs = dispatch(machine, context, base_addr, resolver)?;
+    for pair in &pairs {
+        let start = pair.dst.as_usize();
+        let end = start
+            .checked_add(pair.count)
+            .ok_or(ElfError::AddressOverflow)?;
+        if pair.count > 8
+            || !writable
+                .iter()
+                .any(|range| range.start <= start && end <= range.end)
+        {
+            return Err(ElfError::BadSegment(
+                "relocation outside the writable segments",
+            ));
+        }
+    }
+    for pair in &pairs {
+        let value = pair.src.as_usize() as u64;
+        target.write(pair.dst, &value.to_le_bytes()[..pair.count])?;
+    }
+    Ok(())
+}
+
+/// A relocation entry read from a `REL` or `RELA` table of either ELF class.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RelocEntry {
+    /// Offset of the relocated field from the base address
+    pub offset: usize,
+    /// Index of the referenced symbol in the dynamic symbol table
+    pub sym: u32,
+    /// Type of the relocation
+    pub ty: u32,
+    /// The explicit addend of a `RELA` entry, or the value stored at `offset` for a `REL` entry
+    pub addend: usize,
+}
+
+/// A dynamic symbol of either ELF class.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DynSymbol {
+    /// Offset of the name in `.dynstr`
+    pub name: u32,
+    /// Index of the section which defines the symbol, 0 if it is undefined
+    pub shndx: u16,
+    /// Binding of the symbol, e.g. [`STB_WEAK`]
+    pub bind: u8,
+    /// Value of the symbol
+    pub value: usize,
+}
+
+/// Read the word stored at `vaddr` of the file, which is the implicit addend of a `REL` entry.
+fn read_word(elf: &ElfFile, program_headers: &[ProgramHeaderInfo], vaddr: u64) -> ElfResult<usize> {
+    let class = ElfClass::from_elf(elf)?;
+    let word = class.word_size() as u64;
+    let ph = program_headers
+        .iter()
+        .find(|ph| {
+            ph.type_ == Type::Load
+                && vaddr >= ph.vaddr
+                && vaddr.saturating_add(word) <= ph.vaddr.saturating_add(ph.mem_size)
+        })
+        .ok_or(ElfError::BadSegment("relocation outside the LOAD segments"))?;
+    // The field is in the zero-filled part of the segment.
+    if vaddr - ph.vaddr >= ph.file_size {
+        return Ok(0);
+    }
+    let offset = ph
+        .offset
+        .checked_add(vaddr - ph.vaddr)
+        .ok_or(ElfError::OutOfBounds)?;
+    let bytes = section::file_range(elf, offset, word)?;
+    Ok(read_uint(bytes) as usize)
+}
+
+/// The relocation tables processed by the back-ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RelocTable {
+    /// `DT_RELA`/`DT_REL`, or the `.rela.dyn`/`.rel.dyn` section
+    Dyn,
+    /// `DT_JMPREL`, or the `.rela.plt`/`.rel.plt` section
+    Plt,
+}
+
+/// Read the entries of a relocation table in `data`.
+fn parse_reloc_entries(
+    elf: &ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+    data: &[u8],
+    format: RelocFormat,
+    entry_size: Option<u64>,
+    name: &'static str,
+) -> ElfResult<Vec<RelocEntry>> {
+    let class = ElfClass::from_elf(elf)?;
+    let word = class.word_size();
+    let size = match format {
+        RelocFormat::Rela => word * 3,
+        RelocFormat::Rel => word * 2,
+    };
+    if entry_size.is_some_and(|entry_size| entry_size != size as u64)
+        || !data.len().is_multiple_of(size)
+    {
+        return Err(ElfError::MalformedSection(name));
+    }
+    data.chunks_exact(size)
+        .map(|entry| {
+            let offset = read_uint(&entry[..word]);
+            let info = read_uint(&entry[word..word * 2]);
+            let (sym, ty) = match class {
+                ElfClass::Elf64 => ((info >> 32) as u32, info as u32),
+                ElfClass::Elf32 => ((info >> 8) as u32, (info & 0xff) as u32),
+            };
+            let addend = match format {
+                RelocFormat::Rela => read_uint(&entry[word * 2..]) as usize,
+                RelocFormat::Rel => read_word(elf, program_headers, offset)?,
+            };
+            // The addend is signed, so it is sign-extended for ELF32.
+            let addend = match class {
+                ElfClass::Elf64 => addend,
+                ElfClass::Elf32 => addend as i32 as usize,
+            };
+            Ok(RelocEntry {
+                offset: offset as usize,
+                sym,
+                ty,
+                addend,
+            })
+        })
+        .collect()
+}
+
+/// Read the relocation entries of `table` located by the dynamic segment.
+fn dynamic_reloc_entries(
+    elf: &ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+    info: &DynamicInfo,
+    table: RelocTable,
+) -> ElfResult<Option<Vec<RelocEntry>>> {
+    let (range, format, entry_size) = match table {
+        RelocTable::Dyn => match (info.rela, info.rel) {
+            (Some(rela), _) => (rela, RelocFormat::Rela, info.rela_ent),
+            (None, Some(rel)) => (rel, RelocFormat::Rel, info.rel_ent),
+            (None, None) => return Ok(None),
+        },
+        RelocTable::Plt => match (info.jmprel, info.pltrel) {
+            (Some(jmprel), Some(format)) => (jmprel, format, None),
+            (Some(_), None) => return Err(ElfError::MalformedSection("dynamic segment")),
+            (None, _) => return Ok(None),
+        },
+    };
+    let mut size = range.size;
+    if table == RelocTable::Dyn {
+        // Some linkers include the PLT relocations at the end of the `DT_RELA` table, which
+        // are processed separately.
+        if let Some(jmprel) = info.jmprel {
+            if jmprel.addr > range.addr && jmprel.addr < range.addr.saturating_add(size) {
+                size = jmprel.addr - range.addr;
+            } else if jmprel.addr == range.addr {
+                size = 0;
+            }
+        }
+    }
+    let data = vaddr_range(elf, program_headers, range.addr, size)?;
+    parse_reloc_entries(
+        elf,
+        program_headers,
+        data,
+        format,
+        entry_size,
+        "dynamic segment",
+    )
+    .map(Some)
+}
+
+/// The size of an entry of the dynamic symbol table.
+fn dyn_symbol_size(class: ElfClass) -> usize {
+    match class {
+        ElfClass::Elf64 => 24,
+        ElfClass::Elf32 => 16,
+    }
+}
+
+/// Read the packed relative relocation table (`DT_RELR`), or the `.relr.dyn` section if the
+/// file has no dynamic segment.
+///
+/// Each even entry is the address of a relocated word, and each odd entry is a bitmap of the
+/// relocated words following the last address.
+fn relr_addresses(
+    elf: &ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+    dynamic: Option<&DynamicInfo>,
+) -> ElfResult<Vec<u64>> {
+    let word = ElfClass::from_elf(elf)?.word_size();
+    let (data, name) = match dynamic {
+        Some(info) => {
+            let Some(relr) = info.relr else {
+                return Ok(Vec::new());
+            };
+            if info
+                .relr_ent
+                .is_some_and(|entry_size| entry_size != word as u64)
+            {
+                return Err(ElfError::MalformedSection("dynamic segment"));
+            }
+            let data = vaddr_range(elf, program_headers, relr.addr, relr.size)?;
+            (data, "dynamic segment")
+        }
+        None => match find_section(elf, ".relr.dyn")? {
+            Some(section) => {
+                let data = section::file_range(elf, section.offset(), section.size())?;
+                (data, ".relr.dyn")
+            }
+            None => return Ok(Vec::new()),
+        },
+    };
+    if !data.len().is_multiple_of(word) {
+        return Err(ElfError::MalformedSection(name));
+    }
+    let mut addresses = Vec::new();
+    // The address of the word following the last relocated address.
+    let mut next = None;
+    for entry in data.chunks_exact(word).map(read_uint) {
+        if entry & 1 == 0 {
+            addresses.push(entry);
+            next = Some(entry.wrapping_add(word as u64));
+        } else {
+            let Some(start) = next else {
+                return Err(ElfError::MalformedSection(name));
+            };
+            let mut bitmap = entry >> 1;
+            let mut addr = start;
+            while bitmap != 0 {
+                if bitmap & 1 != 0 {
+                    addresses.push(addr);
+                }
+                bitmap >>= 1;
+                addr = addr.wrapping_add(word as u64);
+            }
+            // Each bitmap covers the words of all its bits but the lowest one.
+            next = Some(start.wrapping_add((word as u64 * 8 - 1) * word as u64));
+        }
+    }
+    Ok(addresses)
+}
+
+/// Read the dynamic symbols in `data`.
+fn parse_dyn_symbols(
+    elf: &ElfFile,
+    data: &[u8],
+    entry_size: Option<u64>,
+    name: &'static str,
+) -> ElfResult<Vec<DynSymbol>> {
+    let class = ElfClass::from_elf(elf)?;
+    let size = dyn_symbol_size(class);
+    if entry_size.is_some_and(|entry_size| entry_size != size as u64)
+        || !data.len().is_multiple_of(size)
+    {
+        return Err(ElfError::MalformedSection(name));
+    }
+    Ok(data
+        .chunks_exact(size)
+        .map(|entry| {
+            let name = read_uint(&entry[..4]) as u32;
+            let (info, shndx, value) = match class {
+                ElfClass::Elf64 => (entry[4], read_uint(&entry[6..8]), read_uint(&entry[8..16])),
+                ElfClass::Elf32 => (
+                    entry[12],
+                    read_uint(&entry[14..16]),
+                    read_uint(&entry[4..8]),
+                ),
+            };
+            DynSymbol {
+                name,
+                shndx: shndx as u16,
+                bind: info >> 4,
+                value: value as usize,
+            }
+        })
+        .collect())
+}
+
+/// Read the dynamic symbol table located by `DT_SYMTAB`, or the `.dynsym` section if the
+/// file has no dynamic segment.
+fn dyn_symbols(
+    elf: &ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+    dynamic: Option<&DynamicInfo>,
+) -> ElfResult<Option<Vec<DynSymbol>>> {
+    if let Some(info) = dynamic {
+        let Some(symtab) = info.symtab else {
+            return Ok(None);
+        };
+        let mut data = vaddr_tail(elf, program_headers, symtab)?;
+        // The size of the table is not recorded, but the string table usually follows it.
+        if let Some(strtab) = info.strtab {
+            if strtab.addr > symtab && strtab.addr - symtab < data.len() as u64 {
+                data = &data[..(strtab.addr - symtab) as usize];
+            }
+        }
+        let size = dyn_symbol_size(ElfClass::from_elf(elf)?);
+        let data = &data[..data.len() - data.len() % size];
+        return parse_dyn_symbols(elf, data, info.syment, "dynamic segment").map(Some);
+    }
+    let Some(section) = find_section(elf, ".dynsym")? else {
+        return Ok(None);
+    };
+    let data = section::file_range(elf, section.offset(), section.size())?;
+    parse_dyn_symbols(elf, data, None, ".dynsym").map(Some)
+}
+
+/// Get the symbol referenced by a relocation entry.
+pub(crate) fn dyn_symbol(table: &[DynSymbol], index: u32) -> ElfResult<&DynSymbol> {
+    table
+        .get(index as usize)
+        .ok_or(ElfError::MalformedSection(".dynsym"))
+}
+
+/// The tables of the elf file used by the relocation back-ends, which are read once for
+/// each relocation pass.
+pub(crate) struct RelocContext<'a, 'b> {
+    /// The elf file
+    pub elf: &'b ElfFile<'a>,
+    program_headers: Cow<'b, [ProgramHeaderInfo]>,
+    dynamic: Option<DynamicInfo>,
+    /// The dynamic symbol table, if the file has one
+    pub symbols: Option<Vec<DynSymbol>>,
+}
+
+impl<'a, 'b> RelocContext<'a, 'b> {
+    /// Read the tables of the elf file.
+    pub fn new(elf: &'b ElfFile<'a>) -> ElfResult<Self> {
+        Self::with_program_headers(elf, Cow::Owned(program_headers(elf)?))
+    }
+
+    /// Read the tables of the elf file whose program headers are already read, e.g. by
+    /// [`crate::ElfImage`].
+    pub fn with_program_headers(
+        elf: &'b ElfFile<'a>,
+        program_headers: Cow<'b, [ProgramHeaderInfo]>,
+    ) -> ElfResult<Self> {
+        let dynamic = dynamic_info(elf, &program_headers)?;
+        let symbols = dyn_symbols(elf, &program_headers, dynamic.as_ref())?;
+        Ok(Self {
+            elf,
+            program_headers,
+            dynamic,
+            symbols,
+        })
+    }
+
+    /// Read the relocation entries of `table`.
+    ///
+    /// The table is located by the dynamic segment like the dynamic linker does, and by the
+    /// section names only if the file has no dynamic segment.
+    pub fn reloc_entries(&self, table: RelocTable) -> ElfResult<Option<Vec<RelocEntry>>> {
+        let elf = self.elf;
+        if let Some(info) = &self.dynamic {
+            return dynamic_reloc_entries(elf, &self.program_headers, info, table);
+        }
+        let (rela, rel) = match table {
+            RelocTable::Dyn => (".rela.dyn", ".rel.dyn"),
+            RelocTable::Plt => (".rela.plt", ".rel.plt"),
+        };
+        for (name, format) in [(rela, RelocFormat::Rela), (rel, RelocFormat::Rel)] {
+            if let Some(section) = find_section(elf, name)? {
+                let data = section::file_range(elf, section.offset(), section.size())?;
+                return parse_reloc_entries(elf, &self.program_headers, data, format, None, name)
+                    .map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read relocate pairs of the packed relative relocations, which are the same for all
+    /// the architectures: the word at each address is adjusted by the base address.
+    pub fn relr_pairs(&self, base_addr: usize) -> ElfResult<Vec<RelocatePair>> {
+        let elf = self.elf;
+        let word = ElfClass::from_elf(elf)?.word_size();
+        relr_addresses(elf, &self.program_headers, self.dynamic.as_ref())?
+            .into_iter()
+            .map(|addr| {
+                let addend = read_word(elf, &self.program_headers, addr)?;
+                Ok(RelocatePair {
+                    src: VirtAddr::from(base_addr.wrapping_add(addend)),
+                    dst: VirtAddr::from(base_addr.wrapping_add(addr as usize)),
+                    count: word,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the name of a dynamic symbol from the string table located by `DT_STRTAB`, or the
+    /// `.dynstr` section if the file has no dynamic segment.
+    fn symbol_name(&self, name: u32) -> ElfResult<&'a str> {
+        if let Some(strtab) = self.dynamic.as_ref().and_then(|info| info.strtab) {
+            let data = vaddr_range(self.elf, &self.program_headers, strtab.addr, strtab.size)?;
+            return section::read_cstr(data, name as usize)
+                .ok_or(ElfError::MalformedSection("dynamic segment"));
+        }
+        section::dyn_symbol_name(self.elf, name)
+    }
+
+    /// Get the runtime address of a symbol when the file is loaded at `base_addr`.
+    ///
+    /// A symbol defined in the file is at `base_addr` plus its value, an undefined one is
+    /// looked up with `resolver`, and a weak undefined one which is not found is 0.
+    pub fn resolve_symbol(
+        &self,
+        symbol: &DynSymbol,
+        base_addr: usize,
+        resolver: &dyn SymbolResolver,
+    ) -> ElfResult<usize> {
+        match symbol.shndx {
+            SHN_UNDEF => {}
+            SHN_ABS => return Ok(symbol.value),
+            _ => return Ok(base_addr.wrapping_add(symbol.value)),
+        }
+        let name = self.symbol_name(symbol.name)?;
+        match resolver.resolve(name) {
+            Some(value) => Ok(value),
+            None if symbol.bind == STB_WEAK => Ok(0),
+            None => Err(ElfError::SymbolNotFound(name.into())),
+        }
     }
 }
@@ -1,13 +1,11 @@
-//! Relocate .rela sections for ELF file under riscv64 architecture.
+//! Relocate .rela sections for ELF file under riscv32 and riscv64 architecture.
 //! riscv: <https://d3s.mff.cuni.cz/files/teaching/nswi200/202324/doc/riscv-abi.pdf>
 
-use core::mem::size_of;
-
-use super::RelocatePair;
+use super::{dyn_symbol, NoResolver, RelocContext, RelocTable, RelocatePair, SymbolResolver};
+use crate::{ElfClass, ElfError, ElfResult};
 use alloc::vec::Vec;
 use log::info;
 use memory_addr::VirtAddr;
-use xmas_elf::symbol_table::Entry;
 extern crate alloc;
 
 const R_RISCV_32: u32 = 1;
@@ -16,135 +14,117 @@ const R_RISCV_RELATIVE: u32 = 3;
 const R_JUMP_SLOT: u32 = 5;
 const TLS_DTPREL32: u32 = 8;
 const TLS_DTV_OFFSET: usize = 0x800;
-/// Read relocate pairs from the elf file.
+/// Read relocate pairs from the elf file, without resolving the undefined symbols.
+///
+/// See [`relocate_pairs_with`].
+pub fn relocate_pairs(elf: &xmas_elf::ElfFile, base_addr: usize) -> ElfResult<Vec<RelocatePair>> {
+    relocate_pairs_with(elf, base_addr, &NoResolver)
+}
+
+/// Read relocate pairs from the elf file, looking up the undefined symbols with `resolver`.
 ///
 /// # Arguments
 ///
 /// * `elf` - The [`xmas_elf::ElfFile`] data
 /// * `base_addr` - The base address of the elf file if the file will be loaded to the memory
+/// * `resolver` - The [`SymbolResolver`] of the symbols undefined in the elf file
 ///
 /// # Return
 /// A vector of [`super::RelocatePair`] which contains the source
 /// and destination address of the relocation.
-pub fn relocate_pairs(elf: &xmas_elf::ElfFile, base_addr: usize) -> Vec<RelocatePair> {
-    let elf_header = elf.header;
-    let magic = elf_header.pt1.magic;
-    assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-    let mut pairs = Vec::new();
-    info!("Base addr for the elf: 0x{:x}", base_addr);
-    if let Some(rela_dyn) = elf.find_section_by_name(".rela.dyn") {
-        let data = match rela_dyn.get_data(elf) {
-            Ok(xmas_elf::sections::SectionData::Rela64(data)) => data,
-            _ => panic!("Invalid data in .rela.dyn section"),
-        };
-
-        if let Some(dyn_sym_table) = elf.find_section_by_name(".dynsym") {
-            let dyn_sym_table = match dyn_sym_table.get_data(elf) {
-                Ok(xmas_elf::sections::SectionData::DynSymbolTable64(dyn_sym_table)) => {
-                    dyn_sym_table
-                }
-                _ => panic!("Invalid data in .dynsym section"),
-            };
+pub fn relocate_pairs_with(
+    elf: &xmas_elf::ElfFile,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    relocate(&RelocContext::new(elf)?, base_addr, resolver)
+}
 
+/// Read relocate pairs from the tables read by `context`. See [`relocate_pairs_with`].
+pub(crate) fn relocate(
+    context: &RelocContext,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    let word = ElfClass::from_elf(context.elf)?.word_size();
+    let mut pairs = context.relr_pairs(base_addr)?;
+    info!("Base addr for the elf: 0x{:x}", base_addr);
+    if let Some(data) = context.reloc_entries(RelocTable::Dyn)? {
+        if let Some(dyn_sym_table) = &context.symbols {
             info!("Relocating .rela.dyn");
-            for entry in data {
-                let dyn_sym = &dyn_sym_table[entry.get_symbol_table_index() as usize];
-                let destination = base_addr + entry.get_offset() as usize;
-                let symbol_value = dyn_sym.value() as usize; // Represents the value of the symbol whose index resides in the relocation entry.
-                let addend = entry.get_addend() as usize; // Represents the addend used to compute the value of the relocatable field.
+            for entry in &data {
+                let dyn_sym = dyn_symbol(dyn_sym_table, entry.sym)?;
+                let destination = base_addr.wrapping_add(entry.offset);
+                let symbol_value = dyn_sym.value; // Represents the value of the symbol whose index resides in the relocation entry.
+                let addend = entry.addend; // Represents the addend used to compute the value of the relocatable field.
 
-                match entry.get_type() {
+                match entry.ty {
                     R_RISCV_32 => {
-                        if dyn_sym.shndx() == 0 {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(symbol_value + addend),
+                            src: VirtAddr::from(symbol_value.wrapping_add(addend)),
                             dst: VirtAddr::from(destination),
                             count: 4,
                         })
                     }
                     R_RISCV_64 => {
-                        if dyn_sym.shndx() == 0 {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(symbol_value + addend),
+                            src: VirtAddr::from(symbol_value.wrapping_add(addend)),
                             dst: VirtAddr::from(destination),
                             count: 8,
                         })
                     }
                     R_RISCV_RELATIVE => pairs.push(RelocatePair {
-                        src: VirtAddr::from(base_addr + addend),
+                        src: VirtAddr::from(base_addr.wrapping_add(addend)),
                         dst: VirtAddr::from(destination),
-                        count: size_of::<usize>() / size_of::<u8>(),
+                        count: word,
                     }),
                     R_JUMP_SLOT => {
-                        if dyn_sym.shndx() == 0 {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
                         pairs.push(RelocatePair {
                             src: VirtAddr::from(symbol_value),
                             dst: VirtAddr::from(destination),
-                            count: size_of::<usize>() / size_of::<u8>(),
+                            count: word,
                         })
                     }
                     TLS_DTPREL32 => pairs.push(RelocatePair {
-                        src: VirtAddr::from(symbol_value + addend - TLS_DTV_OFFSET),
+                        src: VirtAddr::from(
+                            symbol_value
+                                .wrapping_add(addend)
+                                .wrapping_sub(TLS_DTV_OFFSET),
+                        ),
                         dst: VirtAddr::from(destination),
                         count: 4,
                     }),
-                    other => panic!("Unknown relocation type: {}", other),
+                    other => return Err(ElfError::UnsupportedRelocation(other)),
                 }
             }
         }
     }
 
     // Relocate .rela.plt sections
-    if let Some(rela_plt) = elf.find_section_by_name(".rela.plt") {
-        let data = match rela_plt.get_data(elf) {
-            Ok(xmas_elf::sections::SectionData::Rela64(data)) => data,
-            _ => panic!("Invalid data in .rela.plt section"),
-        };
-        if elf.find_section_by_name(".dynsym").is_some() {
-            let dyn_sym_table = match elf
-                .find_section_by_name(".dynsym")
-                .expect("Dynamic Symbol Table not found for .rela.plt section")
-                .get_data(elf)
-            {
-                Ok(xmas_elf::sections::SectionData::DynSymbolTable64(dyn_sym_table)) => {
-                    dyn_sym_table
-                }
-                _ => panic!("Invalid data in .dynsym section"),
-            };
-
+    if let Some(data) = context.reloc_entries(RelocTable::Plt)? {
+        if let Some(dyn_sym_table) = &context.symbols {
             info!("Relocating .rela.plt");
-            for entry in data {
-                let dyn_sym = &dyn_sym_table[entry.get_symbol_table_index() as usize];
-                let destination = base_addr + entry.get_offset() as usize;
-                match entry.get_type() {
+            for entry in &data {
+                let dyn_sym = dyn_symbol(dyn_sym_table, entry.sym)?;
+                let destination = base_addr.wrapping_add(entry.offset);
+                match entry.ty {
                     R_JUMP_SLOT => {
-                        let symbol_value = if dyn_sym.shndx() != 0 {
-                            dyn_sym.value() as usize
-                        } else {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }; // Represents the value of the symbol whose index resides in the relocation entry.
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?; // Represents the value of the symbol whose index resides in the relocation entry.
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(symbol_value + base_addr),
+                            src: VirtAddr::from(symbol_value),
                             dst: VirtAddr::from(destination),
-                            count: size_of::<usize>(),
+                            count: word,
                         });
                     }
-                    other => panic!("Unknown relocation type: {}", other),
+                    other => return Err(ElfError::UnsupportedRelocation(other)),
                 }
             }
         }
     }
 
     info!("Relocating done");
-    pairs
+    Ok(pairs)
 }
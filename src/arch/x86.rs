@@ -0,0 +1,125 @@
+//! Relocate .rel sections for ELF file under i386 architecture.
+//! i386: <https://gitlab.com/x86-psABIs/i386-ABI/-/jobs/artifacts/hjl/x86/master/raw/intel386-psABI.pdf?job=build>
+
+use super::{dyn_symbol, NoResolver, RelocContext, RelocTable, RelocatePair, SymbolResolver};
+use crate::{ElfError, ElfResult};
+use alloc::vec::Vec;
+use log::info;
+use memory_addr::VirtAddr;
+extern crate alloc;
+
+const R_386_32: u32 = 1;
+const R_386_PC32: u32 = 2;
+const R_386_GLOB_DAT: u32 = 6;
+const R_386_JMP_SLOT: u32 = 7;
+const R_386_RELATIVE: u32 = 8;
+
+/// Read relocate pairs from the elf file, without resolving the undefined symbols.
+///
+/// See [`relocate_pairs_with`].
+pub fn relocate_pairs(elf: &xmas_elf::ElfFile, base_addr: usize) -> ElfResult<Vec<RelocatePair>> {
+    relocate_pairs_with(elf, base_addr, &NoResolver)
+}
+
+/// Read relocate pairs from the elf file, looking up the undefined symbols with `resolver`.
+///
+/// The addends of `.rel` entries are read from the relocated fields in the file.
+///
+/// # Arguments
+///
+/// * `elf` - The [`xmas_elf::ElfFile`] data
+/// * `base_addr` - The base address of the elf file if the file will be loaded to the memory
+/// * `resolver` - The [`SymbolResolver`] of the symbols undefined in the elf file
+///
+/// # Return
+/// A vector of [`super::RelocatePair`] which contains the source
+/// and destination address of the relocation.
+pub fn relocate_pairs_with(
+    elf: &xmas_elf::ElfFile,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    relocate(&RelocContext::new(elf)?, base_addr, resolver)
+}
+
+/// Read relocate pairs from the tables read by `context`. See [`relocate_pairs_with`].
+pub(crate) fn relocate(
+    context: &RelocContext,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    let mut pairs = context.relr_pairs(base_addr)?;
+    info!("Base addr for the elf: 0x{:x}", base_addr);
+    if let Some(data) = context.reloc_entries(RelocTable::Dyn)? {
+        if let Some(dyn_sym_table) = &context.symbols {
+            info!("Relocating .rel.dyn");
+            for entry in &data {
+                let dyn_sym = dyn_symbol(dyn_sym_table, entry.sym)?;
+                let destination = base_addr.wrapping_add(entry.offset);
+                let addend = entry.addend;
+                match entry.ty {
+                    R_386_32 => {
+                        // S + A, where S is the runtime address of the symbol.
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
+                        pairs.push(RelocatePair {
+                            src: VirtAddr::from(symbol_value.wrapping_add(addend)),
+                            dst: VirtAddr::from(destination),
+                            count: 4,
+                        })
+                    }
+                    R_386_PC32 => {
+                        // S + A - P
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
+                        pairs.push(RelocatePair {
+                            src: VirtAddr::from(
+                                symbol_value.wrapping_add(addend).wrapping_sub(destination),
+                            ),
+                            dst: VirtAddr::from(destination),
+                            count: 4,
+                        })
+                    }
+                    R_386_GLOB_DAT | R_386_JMP_SLOT => {
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
+                        pairs.push(RelocatePair {
+                            src: VirtAddr::from(symbol_value),
+                            dst: VirtAddr::from(destination),
+                            count: 4,
+                        })
+                    }
+                    R_386_RELATIVE => pairs.push(RelocatePair {
+                        src: VirtAddr::from(base_addr.wrapping_add(addend)),
+                        dst: VirtAddr::from(destination),
+                        count: 4,
+                    }),
+                    other => return Err(ElfError::UnsupportedRelocation(other)),
+                }
+            }
+        }
+    }
+
+    // Relocate .rel.plt sections
+    if let Some(data) = context.reloc_entries(RelocTable::Plt)? {
+        if let Some(dyn_sym_table) = &context.symbols {
+            info!("Relocating .rel.plt");
+            for entry in &data {
+                let dyn_sym = dyn_symbol(dyn_sym_table, entry.sym)?;
+                let destination = base_addr.wrapping_add(entry.offset);
+                match entry.ty {
+                    // The stored value is the address of the lazy binding stub, which is not an addend.
+                    R_386_JMP_SLOT => {
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
+                        pairs.push(RelocatePair {
+                            src: VirtAddr::from(symbol_value),
+                            dst: VirtAddr::from(destination),
+                            count: 4,
+                        });
+                    }
+                    other => return Err(ElfError::UnsupportedRelocation(other)),
+                }
+            }
+        }
+    }
+
+    info!("Relocating done");
+    Ok(pairs)
+}
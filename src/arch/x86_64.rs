@@ -1,12 +1,13 @@
 //! Relocate .rela sections for ELF file under x86_64 architecture.
 //! x86_64: <https://gitlab.com/x86-psABIs/x86-64-ABI/-/jobs/artifacts/master/raw/x86-64-ABI/abi.pdf?job=build>
-use core::mem::size_of;
-
-use super::RelocatePair;
+use super::{dyn_symbol, NoResolver, RelocContext, RelocTable, RelocatePair, SymbolResolver};
+use crate::{
+    note::{GNU_PROPERTY_X86_FEATURE_1_IBT, GNU_PROPERTY_X86_FEATURE_1_SHSTK},
+    ElfClass, ElfError, ElfResult, ExecPlan, GnuProperties,
+};
 use alloc::vec::Vec;
 use log::info;
 use memory_addr::VirtAddr;
-use xmas_elf::symbol_table::Entry;
 extern crate alloc;
 
 const R_X86_64_64: u32 = 1;
@@ -17,80 +18,127 @@ const R_X86_64_RELATIVE: u32 = 8;
 
 const R_X86_64_IRELATIVE: u32 = 37;
 
-/// Read relocate pairs from the elf file.
+/// The largest shadow stack allocated by default, the same as Linux.
+const SHSTK_MAX_SIZE: u64 = 1 << 32;
+
+/// The Control-flow Enforcement Technology features a process may enable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CetFeatures {
+    /// Indirect Branch Tracking
+    pub ibt: bool,
+    /// Shadow stack
+    pub shstk: bool,
+}
+
+impl CetFeatures {
+    /// Read the CET features an x86_64 image is compatible with.
+    pub fn from_properties(properties: &GnuProperties) -> Self {
+        Self {
+            ibt: properties.x86_features & GNU_PROPERTY_X86_FEATURE_1_IBT != 0,
+            shstk: properties.x86_features & GNU_PROPERTY_X86_FEATURE_1_SHSTK != 0,
+        }
+    }
+
+    /// The features both `self` and `other` are compatible with.
+    pub fn and(self, other: Self) -> Self {
+        Self {
+            ibt: self.ibt && other.ibt,
+            shstk: self.shstk && other.shstk,
+        }
+    }
+
+    /// The features which may be enabled for the process of `plan`, which are supported by
+    /// both the main program and the interpreter.
+    pub fn from_plan(plan: &ExecPlan) -> Self {
+        let main = Self::from_properties(&plan.main_properties);
+        match &plan.interp_properties {
+            Some(properties) => main.and(Self::from_properties(properties)),
+            None => main,
+        }
+    }
+}
+
+/// Size of the shadow stack of the main thread, which is the stack size limit
+/// (`RLIMIT_STACK`) capped at 4G and rounded up to `page_size`, as Linux does.
+pub fn shadow_stack_size(stack_limit: usize, page_size: usize) -> usize {
+    ((stack_limit as u64).min(SHSTK_MAX_SIZE) as usize).next_multiple_of(page_size)
+}
+
+/// Read relocate pairs from the elf file, without resolving the undefined symbols.
+///
+/// See [`relocate_pairs_with`].
+pub fn relocate_pairs(elf: &xmas_elf::ElfFile, base_addr: usize) -> ElfResult<Vec<RelocatePair>> {
+    relocate_pairs_with(elf, base_addr, &NoResolver)
+}
+
+/// Read relocate pairs from the elf file, looking up the undefined symbols with `resolver`.
 ///
 /// # Arguments
 ///
 /// * `elf` - The [`xmas_elf::ElfFile`] data
 /// * `base_addr` - The base address of the elf file if the file will be loaded to the memory
+/// * `resolver` - The [`SymbolResolver`] of the symbols undefined in the elf file
 ///
 /// # Return
 /// A vector of [`super::RelocatePair`] which contains the source
 /// and destination address of the relocation.
-pub fn relocate_pairs(elf: &xmas_elf::ElfFile, base_addr: usize) -> Vec<RelocatePair> {
-    let elf_header = elf.header;
-    let magic = elf_header.pt1.magic;
-    assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-    let mut pairs = Vec::new();
-    info!("Base addr for the elf: 0x{:x}", base_addr);
-    if let Some(rela_dyn) = elf.find_section_by_name(".rela.dyn") {
-        let data = match rela_dyn.get_data(elf) {
-            Ok(xmas_elf::sections::SectionData::Rela64(data)) => data,
-            _ => panic!("Invalid data in .rela.dyn section"),
-        };
+pub fn relocate_pairs_with(
+    elf: &xmas_elf::ElfFile,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    relocate(&RelocContext::new(elf)?, base_addr, resolver)
+}
 
-        if let Some(dyn_sym_table) = elf.find_section_by_name(".dynsym") {
-            let dyn_sym_table = match dyn_sym_table.get_data(elf) {
-                Ok(xmas_elf::sections::SectionData::DynSymbolTable64(dyn_sym_table)) => {
-                    dyn_sym_table
-                }
-                _ => panic!("Invalid data in .dynsym section"),
-            };
+/// Read relocate pairs from the tables read by `context`. See [`relocate_pairs_with`].
+pub(crate) fn relocate(
+    context: &RelocContext,
+    base_addr: usize,
+    resolver: &dyn SymbolResolver,
+) -> ElfResult<Vec<RelocatePair>> {
+    let word = ElfClass::from_elf(context.elf)?.word_size();
+    let mut pairs = context.relr_pairs(base_addr)?;
+    info!("Base addr for the elf: 0x{:x}", base_addr);
+    if let Some(data) = context.reloc_entries(RelocTable::Dyn)? {
+        if let Some(dyn_sym_table) = &context.symbols {
             info!("Relocating .rela.dyn");
-            for entry in data {
-                let dyn_sym = &dyn_sym_table[entry.get_symbol_table_index() as usize];
-                let offset = entry.get_offset() as usize;
-                let destination = base_addr + offset;
-                let symbol_value = dyn_sym.value() as usize; // Represents the value of the symbol whose index resides in the relocation entry.
-                let addend = entry.get_addend() as usize; // Represents the addend used to compute the value of the relocatable field.
-                match entry.get_type() {
+            for entry in &data {
+                let dyn_sym = dyn_symbol(dyn_sym_table, entry.sym)?;
+                let offset = entry.offset;
+                let destination = base_addr.wrapping_add(offset);
+                let addend = entry.addend; // Represents the addend used to compute the value of the relocatable field.
+                match entry.ty {
                     R_X86_64_64 => {
-                        if dyn_sym.shndx() == 0 {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        };
+                        // S + A
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(symbol_value),
+                            src: VirtAddr::from(symbol_value.wrapping_add(addend)),
                             dst: VirtAddr::from(destination),
-                            count: size_of::<usize>() / size_of::<u8>(),
+                            count: word,
                         })
                     }
                     R_X86_64_PC32 => {
-                        if dyn_sym.shndx() == 0 {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
                         pairs.push(RelocatePair {
-                            src: VirtAddr::from(symbol_value + addend - offset),
+                            src: VirtAddr::from(
+                                symbol_value.wrapping_add(addend).wrapping_sub(destination),
+                            ),
                             dst: VirtAddr::from(destination),
                             count: 4,
                         })
                     }
                     R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => {
-                        if dyn_sym.shndx() == 0 {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        };
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?;
                         pairs.push(RelocatePair {
                             src: VirtAddr::from(symbol_value),
                             dst: VirtAddr::from(destination),
-                            count: size_of::<usize>() / size_of::<u8>(),
+                            count: word,
                         })
                     }
                     R_X86_64_RELATIVE => pairs.push(RelocatePair {
-                        src: VirtAddr::from(base_addr + addend),
+                        src: VirtAddr::from(base_addr.wrapping_add(addend)),
                         dst: VirtAddr::from(destination),
-                        count: size_of::<usize>() / size_of::<u8>(),
+                        count: word,
                     }),
 
                     R_X86_64_IRELATIVE => {
@@ -99,57 +147,37 @@ pub fn relocate_pairs(elf: &xmas_elf::ElfFile, base_addr: usize) -> Vec<Relocate
                         pairs.push(RelocatePair {
                             src: VirtAddr::from(value),
                             dst: VirtAddr::from(destination),
-                            count: size_of::<usize>() / size_of::<u8>(),
+                            count: word,
                         });
                     }
-                    other => panic!("Unknown relocation type: {}", other),
+                    other => return Err(ElfError::UnsupportedRelocation(other)),
                 }
             }
         }
     }
 
     // Relocate .rela.plt sections
-    if let Some(rela_plt) = elf.find_section_by_name(".rela.plt") {
-        let data = match rela_plt.get_data(elf) {
-            Ok(xmas_elf::sections::SectionData::Rela64(data)) => data,
-            _ => panic!("Invalid data in .rela.plt section"),
-        };
-        if elf.find_section_by_name(".dynsym").is_some() {
-            let dyn_sym_table = match elf
-                .find_section_by_name(".dynsym")
-                .expect("Dynamic Symbol Table not found for .rela.plt section")
-                .get_data(elf)
-            {
-                Ok(xmas_elf::sections::SectionData::DynSymbolTable64(dyn_sym_table)) => {
-                    dyn_sym_table
-                }
-                _ => panic!("Invalid data in .dynsym section"),
-            };
-
+    if let Some(data) = context.reloc_entries(RelocTable::Plt)? {
+        if let Some(dyn_sym_table) = &context.symbols {
             info!("Relocating .rela.plt");
-            for entry in data {
-                match entry.get_type() {
+            for entry in &data {
+                match entry.ty {
                     R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => {
-                        let dyn_sym = &dyn_sym_table[entry.get_symbol_table_index() as usize];
-                        let destination = base_addr + entry.get_offset() as usize;
-                        let symbol_value = if dyn_sym.shndx() != 0 {
-                            dyn_sym.value() as usize
-                        } else {
-                            let name = dyn_sym.get_name(elf).unwrap();
-                            panic!(r#"Symbol "{}" not found"#, name);
-                        }; // Represents the value of the symbol whose index resides in the relocation entry.
+                        let dyn_sym = dyn_symbol(dyn_sym_table, entry.sym)?;
+                        let destination = base_addr.wrapping_add(entry.offset);
+                        let symbol_value = context.resolve_symbol(dyn_sym, base_addr, resolver)?; // Represents the value of the symbol whose index resides in the relocation entry.
                         pairs.push(RelocatePair {
                             src: VirtAddr::from(symbol_value),
                             dst: VirtAddr::from(destination),
-                            count: size_of::<usize>() / size_of::<u8>(),
+                            count: word,
                         })
                     }
-                    other => panic!("Unknown relocation type: {}", other),
+                    other => return Err(ElfError::UnsupportedRelocation(other)),
                 }
             }
         }
     }
 
     info!("Relocating done");
-    pairs
+    Ok(pairs)
 }
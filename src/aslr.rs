@@ -0,0 +1,146 @@
+//! Randomization of the base addresses of position-independent images (ASLR).
+use core::ops::Range;
+
+use crate::{ElfError, ElfResult};
+
+/// The `personality` flag which disables the address space randomization of a process.
+pub const ADDR_NO_RANDOMIZE: u32 = 0x0040000;
+
+/// A source of random numbers for [`AslrPolicy::pick_base`], such as a hardware RNG or
+/// the entropy pool of the kernel.
+pub trait EntropySource {
+    /// Return a random 64-bit number.
+    fn next_u64(&mut self) -> u64;
+}
+
+/// What a randomized base address is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseKind {
+    /// The main program, which is placed upwards from the start of its window
+    Executable,
+    /// The dynamic linker, which is placed downwards from the end of its window
+    Interpreter,
+    /// The top of the `mmap` area, which grows downwards from the end of its window
+    MmapBase,
+}
+
+/// The window a base address is picked from, and the number of random bits of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AslrRange {
+    /// Addresses the whole image must fit in
+    pub window: Range<usize>,
+    /// Number of random bits, counted in units of the alignment (`mmap_rnd_bits` in Linux)
+    pub entropy_bits: u32,
+}
+
+/// Where the base addresses of a process are placed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AslrPolicy {
+    /// The range of the main position-independent executable
+    pub executable: AslrRange,
+    /// The range of the dynamic linker
+    pub interpreter: AslrRange,
+    /// The range of the top of the `mmap` area
+    pub mmap_base: AslrRange,
+    /// Size of the range the start of the program break is randomized in, which is 0 if
+    /// it is not randomized (32M on 64-bit Linux)
+    pub brk_range: usize,
+    /// Whether the base addresses are randomized. If not, the lowest address of the window
+    /// is used for [`BaseKind::Executable`] and the highest one for the others.
+    pub randomize: bool,
+}
+
+impl AslrPolicy {
+    /// Create a policy which randomizes the base addresses in the given ranges.
+    pub fn new(executable: AslrRange, interpreter: AslrRange, mmap_base: AslrRange) -> Self {
+        Self {
+            executable,
+            interpreter,
+            mmap_base,
+            brk_range: 0,
+            randomize: true,
+        }
+    }
+
+    /// Randomize the start of the program break in `brk_range` bytes.
+    pub fn with_brk_range(mut self, brk_range: usize) -> Self {
+        self.brk_range = brk_range;
+        self
+    }
+
+    /// Apply the `personality` of the process, which disables the randomization if it
+    /// contains [`ADDR_NO_RANDOMIZE`].
+    pub fn with_personality(mut self, personality: u32) -> Self {
+        self.randomize = personality & ADDR_NO_RANDOMIZE == 0;
+        self
+    }
+
+    /// Pick a base address for an image of `span` bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What the address is used for, which selects the range
+    /// * `span` - The size of the image in the memory, which is 0 for [`BaseKind::MmapBase`]
+    /// * `align` - The alignment of the base, which is the largest `p_align` of the image and
+    ///   at least the page size
+    /// * `entropy` - The source of random numbers
+    ///
+    /// # Return
+    ///
+    /// The base address aligned to `align`, or [`ElfError::NoAddressSpace`] if the image
+    /// does not fit in the window.
+    pub fn pick_base(
+        &self,
+        kind: BaseKind,
+        span: usize,
+        align: usize,
+        entropy: &mut impl EntropySource,
+    ) -> ElfResult<usize> {
+        let range = match kind {
+            BaseKind::Executable => &self.executable,
+            BaseKind::Interpreter => &self.interpreter,
+            BaseKind::MmapBase => &self.mmap_base,
+        };
+        let align = align.max(1);
+        if !align.is_power_of_two() {
+            return Err(ElfError::BadSegment("alignment is not a power of two"));
+        }
+        let lowest = range
+            .window
+            .start
+            .checked_next_multiple_of(align)
+            .ok_or(ElfError::NoAddressSpace)?;
+        let highest = range
+            .window
+            .end
+            .checked_sub(span)
+            .map(|addr| addr - addr % align)
+            .filter(|&addr| addr >= lowest)
+            .ok_or(ElfError::NoAddressSpace)?;
+
+        let offset = if self.randomize {
+            let slots = (((highest - lowest) / align) as u64).saturating_add(1);
+            let slots = match 1u64.checked_shl(range.entropy_bits) {
+                Some(limit) => slots.min(limit),
+                None => slots,
+            };
+            (entropy.next_u64() % slots) as usize * align
+        } else {
+            0
+        };
+        Ok(match kind {
+            BaseKind::Executable => lowest + offset,
+            BaseKind::Interpreter | BaseKind::MmapBase => highest - offset,
+        })
+    }
+
+    /// Pick a page-aligned offset below `brk_range` to add to the start of the program break,
+    /// like `arch_randomize_brk` in Linux. It is 0 if the randomization is disabled.
+    pub fn brk_offset(&self, page_size: usize, entropy: &mut impl EntropySource) -> usize {
+        let pages = (self.brk_range / page_size.max(1)) as u64;
+        if !self.randomize || pages == 0 {
+            return 0;
+        }
+        (entropy.next_u64() % pages) as usize * page_size
+    }
+}
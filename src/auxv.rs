@@ -1,16 +1,17 @@
 //! Some constant in the elf file
 extern crate alloc;
 use alloc::collections::BTreeMap;
-use memory_addr::PAGE_SIZE_4K;
+
+use xmas_elf::program::Type;
+
+use crate::{image::program_headers, ElfError, ElfResult, LoadConfig, ProgramHeaderInfo};
 
 const AT_PHDR: u8 = 3;
 const AT_PHENT: u8 = 4;
 const AT_PHNUM: u8 = 5;
 const AT_PAGESZ: u8 = 6;
-#[allow(unused)]
-const AT_BASE: u8 = 7;
-#[allow(unused)]
-const AT_ENTRY: u8 = 9;
+pub(crate) const AT_BASE: u8 = 7;
+pub(crate) const AT_ENTRY: u8 = 9;
 const AT_RANDOM: u8 = 25;
 
 /// Read auxiliary vectors from the ELF file.
@@ -24,25 +25,65 @@ const AT_RANDOM: u8 = 25;
 /// It will return a `BTreeMap<u8, usize>` which contains the auxiliary vectors. The key is the entry type, and the value is the value of the auxiliary vector.
 ///
 /// Details about auxiliary vectors are described in <https://articles.manugarg.com/aboutelfauxiliaryvectors.html>
-pub fn auxv_vector(elf: &xmas_elf::ElfFile, base_addr: usize) -> BTreeMap<u8, usize> {
+pub fn auxv_vector(elf: &xmas_elf::ElfFile, base_addr: usize) -> ElfResult<BTreeMap<u8, usize>> {
+    auxv_from(
+        elf,
+        &program_headers(elf)?,
+        base_addr,
+        &LoadConfig::default(),
+    )
+}
+
+pub(crate) fn auxv_from(
+    elf: &xmas_elf::ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+    base_addr: usize,
+    config: &LoadConfig,
+) -> ElfResult<BTreeMap<u8, usize>> {
     let mut map = BTreeMap::new();
 
-    if let Some(ph) = elf
-        .program_iter()
-        .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
-    {
-        // The first LOAD segment is the lowest one. And its virtual address is the base address of the ELF file.
-        map.insert(
-            AT_PHDR,
-            base_addr + (ph.virtual_addr() + elf.header.pt2.ph_offset()) as usize,
-        );
-    } else {
-        map.insert(AT_PHDR, 0);
-    }
+    map.insert(AT_PHDR, phdr_addr(elf, program_headers, base_addr)?);
 
     map.insert(AT_PHENT, elf.header.pt2.ph_entry_size() as usize);
     map.insert(AT_PHNUM, elf.header.pt2.ph_count() as usize);
     map.insert(AT_RANDOM, 0);
-    map.insert(AT_PAGESZ, PAGE_SIZE_4K);
-    map
+    map.insert(AT_PAGESZ, config.page_size());
+    Ok(map)
+}
+
+/// Address of the program header table in the memory, which is reported by `AT_PHDR`.
+///
+/// It is the address of the `PT_PHDR` segment if there is one. Otherwise the table must be
+/// in the file range of a `LOAD` segment, and its offset is translated to the address.
+fn phdr_addr(
+    elf: &xmas_elf::ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+    base_addr: usize,
+) -> ElfResult<usize> {
+    let vaddr = if let Some(ph) = program_headers.iter().find(|ph| ph.type_ == Type::Phdr) {
+        ph.vaddr
+    } else {
+        let pt2 = &elf.header.pt2;
+        let ph_offset = pt2.ph_offset();
+        let ph_end = ph_offset
+            .checked_add(pt2.ph_count() as u64 * pt2.ph_entry_size() as u64)
+            .ok_or(ElfError::AddressOverflow)?;
+        let ph = program_headers
+            .iter()
+            .filter(|ph| ph.type_ == Type::Load)
+            .find(|ph| {
+                ph.offset <= ph_offset
+                    && ph
+                        .offset
+                        .checked_add(ph.file_size)
+                        .is_some_and(|end| ph_end <= end)
+            })
+            .ok_or(ElfError::PhdrNotMapped)?;
+        (ph_offset - ph.offset)
+            .checked_add(ph.vaddr)
+            .ok_or(ElfError::AddressOverflow)?
+    };
+    (vaddr as usize)
+        .checked_add(base_addr)
+        .ok_or(ElfError::AddressOverflow)
 }
@@ -0,0 +1,72 @@
+//! Options of loading an ELF file, which are decided by the kernel.
+use memory_addr::PAGE_SIZE_4K;
+
+/// Options of loading an ELF file.
+///
+/// The free functions of this crate use [`LoadConfig::default`], while an [`crate::ElfImage`]
+/// uses the config given by [`crate::ElfImage::with_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadConfig {
+    page_size: usize,
+    deny_wx: bool,
+    user_limit: Option<usize>,
+}
+
+impl LoadConfig {
+    /// The default config, which maps the segments with 4K pages.
+    pub const fn new() -> Self {
+        Self {
+            page_size: PAGE_SIZE_4K,
+            deny_wx: false,
+            user_limit: None,
+        }
+    }
+
+    /// Set the size of the pages the segments are mapped with (e.g. 16K or 64K granules on
+    /// aarch64). It is also reported to the user by `AT_PAGESZ`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page_size` is not a power of two.
+    pub const fn with_page_size(mut self, page_size: usize) -> Self {
+        assert!(
+            page_size.is_power_of_two(),
+            "page size must be a power of two"
+        );
+        self.page_size = page_size;
+        self
+    }
+
+    /// Reject the segments which are both writable and executable (W^X).
+    pub const fn with_deny_wx(mut self, deny_wx: bool) -> Self {
+        self.deny_wx = deny_wx;
+        self
+    }
+
+    /// Reject the segments which end above `limit`, the end of the user address space.
+    pub const fn with_user_limit(mut self, limit: usize) -> Self {
+        self.user_limit = Some(limit);
+        self
+    }
+
+    /// The size of the pages the segments are mapped with.
+    pub const fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Whether writable and executable segments are rejected.
+    pub const fn deny_wx(&self) -> bool {
+        self.deny_wx
+    }
+
+    /// The end of the user address space, if the segments are checked against it.
+    pub const fn user_limit(&self) -> Option<usize> {
+        self.user_limit
+    }
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
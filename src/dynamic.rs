@@ -0,0 +1,166 @@
+//! The dynamic segment (`PT_DYNAMIC`), which locates the tables used by the dynamic linker.
+//!
+//! The tables are found by their addresses rather than by the section names, so the
+//! relocations of a file without section headers can still be read.
+use xmas_elf::program::Type;
+
+use crate::{section, ElfClass, ElfError, ElfResult, ProgramHeaderInfo};
+
+const DT_NULL: u64 = 0;
+const DT_PLTRELSZ: u64 = 2;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_STRSZ: u64 = 10;
+const DT_SYMENT: u64 = 11;
+const DT_REL: u64 = 17;
+const DT_RELSZ: u64 = 18;
+const DT_RELENT: u64 = 19;
+const DT_PLTREL: u64 = 20;
+const DT_JMPREL: u64 = 23;
+const DT_RELRSZ: u64 = 35;
+const DT_RELR: u64 = 36;
+const DT_RELRENT: u64 = 37;
+
+/// The format of the entries of a relocation table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RelocFormat {
+    /// `RELA` entries with an explicit addend
+    Rela,
+    /// `REL` entries whose addend is stored in the relocated field
+    Rel,
+}
+
+/// A table located by the dynamic segment, given by its virtual address and size.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DynTable {
+    pub addr: u64,
+    pub size: u64,
+}
+
+/// The tables used for relocation, read from the dynamic segment.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DynamicInfo {
+    pub rela: Option<DynTable>,
+    pub rela_ent: Option<u64>,
+    pub rel: Option<DynTable>,
+    pub rel_ent: Option<u64>,
+    pub jmprel: Option<DynTable>,
+    pub relr: Option<DynTable>,
+    pub relr_ent: Option<u64>,
+    pub pltrel: Option<RelocFormat>,
+    pub symtab: Option<u64>,
+    pub syment: Option<u64>,
+    pub strtab: Option<DynTable>,
+}
+
+/// Read the dynamic segment of the elf file, if it has one.
+pub(crate) fn dynamic_info(
+    elf: &xmas_elf::ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+) -> ElfResult<Option<DynamicInfo>> {
+    let Some(ph) = program_headers.iter().find(|ph| ph.type_ == Type::Dynamic) else {
+        return Ok(None);
+    };
+    let data = section::file_range(elf, ph.offset, ph.file_size)?;
+    let word = ElfClass::from_elf(elf)?.word_size();
+
+    let mut info = DynamicInfo::default();
+    let (mut rela_size, mut rel_size, mut jmprel_size) = (None, None, None);
+    let (mut rela_addr, mut rel_addr, mut jmprel_addr) = (None, None, None);
+    let (mut relr_size, mut str_size, mut relr_addr, mut str_addr) = (None, None, None, None);
+    for entry in data.chunks_exact(word * 2) {
+        let (tag, value) = entry.split_at(word);
+        let (tag, value) = (read_uint(tag), read_uint(value));
+        match tag {
+            DT_NULL => break,
+            DT_PLTRELSZ => jmprel_size = Some(value),
+            DT_STRTAB => str_addr = Some(value),
+            DT_SYMTAB => info.symtab = Some(value),
+            DT_RELA => rela_addr = Some(value),
+            DT_RELASZ => rela_size = Some(value),
+            DT_RELAENT => info.rela_ent = Some(value),
+            DT_STRSZ => str_size = Some(value),
+            DT_SYMENT => info.syment = Some(value),
+            DT_REL => rel_addr = Some(value),
+            DT_RELSZ => rel_size = Some(value),
+            DT_RELENT => info.rel_ent = Some(value),
+            DT_PLTREL => {
+                info.pltrel = Some(match value {
+                    DT_RELA => RelocFormat::Rela,
+                    DT_REL => RelocFormat::Rel,
+                    _ => return Err(ElfError::MalformedSection("dynamic segment")),
+                })
+            }
+            DT_JMPREL => jmprel_addr = Some(value),
+            DT_RELRSZ => relr_size = Some(value),
+            DT_RELR => relr_addr = Some(value),
+            DT_RELRENT => info.relr_ent = Some(value),
+            _ => {}
+        }
+    }
+    let table = |addr: Option<u64>, size: Option<u64>| {
+        addr.map(|addr| DynTable {
+            addr,
+            size: size.unwrap_or(0),
+        })
+    };
+    info.rela = table(rela_addr, rela_size);
+    info.rel = table(rel_addr, rel_size);
+    info.jmprel = table(jmprel_addr, jmprel_size);
+    info.relr = table(relr_addr, relr_size);
+    info.strtab = table(str_addr, str_size);
+    Ok(Some(info))
+}
+
+/// Read a little-endian unsigned integer of at most 8 bytes.
+pub(crate) fn read_uint(bytes: &[u8]) -> u64 {
+    let mut value = [0u8; 8];
+    value[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(value)
+}
+
+/// Get the file data of `size` bytes loaded at `vaddr`, which must be in the file-backed part
+/// of a `LOAD` segment.
+pub(crate) fn vaddr_range<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+    program_headers: &[ProgramHeaderInfo],
+    vaddr: u64,
+    size: u64,
+) -> ElfResult<&'a [u8]> {
+    let end = vaddr.checked_add(size).ok_or(ElfError::AddressOverflow)?;
+    let ph = program_headers
+        .iter()
+        .find(|ph| {
+            ph.type_ == Type::Load
+                && vaddr >= ph.vaddr
+                && end <= ph.vaddr.saturating_add(ph.file_size)
+        })
+        .ok_or(ElfError::MalformedSection("dynamic segment"))?;
+    let offset = ph
+        .offset
+        .checked_add(vaddr - ph.vaddr)
+        .ok_or(ElfError::OutOfBounds)?;
+    section::file_range(elf, offset, size)
+}
+
+/// Get the file data loaded from `vaddr` to the end of the file-backed part of its `LOAD`
+/// segment, for a table whose size is not given by the dynamic segment.
+pub(crate) fn vaddr_tail<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+    program_headers: &[ProgramHeaderInfo],
+    vaddr: u64,
+) -> ElfResult<&'a [u8]> {
+    let ph = program_headers
+        .iter()
+        .find(|ph| {
+            ph.type_ == Type::Load
+                && vaddr >= ph.vaddr
+                && vaddr < ph.vaddr.saturating_add(ph.file_size)
+        })
+        .ok_or(ElfError::MalformedSection("dynamic segment"))?;
+    let end = ph.vaddr.saturating_add(ph.file_size);
+    vaddr_range(elf, program_headers, vaddr, end - vaddr)
+}
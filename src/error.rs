@@ -0,0 +1,106 @@
+//! Errors reported while parsing and loading an ELF file.
+extern crate alloc;
+use alloc::string::String;
+
+use crate::validate::{SegmentDiagnostic, SegmentIssue};
+use axerrno::{AxError, LinuxError};
+use core::fmt;
+
+/// A specialized [`Result`] type with [`ElfError`] as the error type.
+pub type ElfResult<T = ()> = Result<T, ElfError>;
+
+/// The error type for every fallible operation in this crate.
+///
+/// It can be converted into [`AxError`] or [`LinuxError`], so that the caller
+/// (e.g. `execve`) can report `ENOEXEC`, `EINVAL`, `ENOMEM` or `E2BIG` to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    /// The file does not start with the ELF magic number.
+    BadMagic,
+    /// The ELF class is neither ELF32 nor ELF64, or does not match the header layout.
+    BadClass,
+    /// The data encoding is not little endian.
+    BadEncoding,
+    /// The ELF version is not the current version.
+    BadVersion,
+    /// The machine type (`e_machine`) is not supported.
+    BadMachine(u16),
+    /// The file is neither an executable nor a shared object.
+    BadType,
+    /// A header table, segment or section lies (partly) outside the file.
+    OutOfBounds,
+    /// A table in the file is not aligned for its entry type.
+    Misaligned,
+    /// The content of a section is malformed.
+    MalformedSection(&'static str),
+    /// A `LOAD` segment can not be mapped as described.
+    BadSegment(&'static str),
+    /// A `LOAD` segment is rejected by [`crate::validate_segments`].
+    InvalidSegment(SegmentDiagnostic),
+    /// No `LOAD` segment is found in the file.
+    NoLoadSegment,
+    /// The program header table is not loaded into the memory, so `AT_PHDR` is unknown.
+    PhdrNotMapped,
+    /// The `PT_TLS` segment is malformed.
+    BadTls(&'static str),
+    /// The path in the `PT_INTERP` segment is not a valid NUL-terminated string.
+    BadInterpreter,
+    /// The relocation type is not supported by the architecture back-end.
+    UnsupportedRelocation(u32),
+    /// A symbol referenced by a relocation is not defined.
+    SymbolNotFound(String),
+    /// An address computed from the file overflows.
+    AddressOverflow,
+    /// The image does not fit in the address window it is placed in.
+    NoAddressSpace,
+    /// The initial stack frame does not fit into the user stack.
+    StackTooSmall,
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "invalid ELF magic"),
+            Self::BadClass => write!(f, "invalid ELF class"),
+            Self::BadEncoding => write!(f, "unsupported ELF data encoding"),
+            Self::BadVersion => write!(f, "unsupported ELF version"),
+            Self::BadMachine(machine) => write!(f, "unsupported machine type: {:#x}", machine),
+            Self::BadType => write!(f, "not an executable or shared object"),
+            Self::OutOfBounds => write!(f, "data out of the file bounds"),
+            Self::Misaligned => write!(f, "misaligned ELF table"),
+            Self::MalformedSection(name) => write!(f, "invalid data in {} section", name),
+            Self::BadSegment(msg) => write!(f, "invalid LOAD segment: {}", msg),
+            Self::InvalidSegment(diagnostic) => write!(f, "invalid {}", diagnostic),
+            Self::NoLoadSegment => write!(f, "no LOAD segment found"),
+            Self::PhdrNotMapped => write!(f, "program header table is not in any LOAD segment"),
+            Self::BadTls(msg) => write!(f, "invalid TLS segment: {}", msg),
+            Self::BadInterpreter => write!(f, "invalid interpreter path"),
+            Self::UnsupportedRelocation(ty) => write!(f, "unknown relocation type: {}", ty),
+            Self::SymbolNotFound(name) => write!(f, r#"symbol "{}" not found"#, name),
+            Self::AddressOverflow => write!(f, "address overflow"),
+            Self::NoAddressSpace => write!(f, "no space in the address window for the image"),
+            Self::StackTooSmall => write!(f, "initial stack frame exceeds the stack size"),
+        }
+    }
+}
+
+impl From<ElfError> for AxError {
+    fn from(err: ElfError) -> Self {
+        match err {
+            ElfError::AddressOverflow | ElfError::BadSegment(_) => AxError::InvalidInput,
+            ElfError::InvalidSegment(SegmentDiagnostic {
+                issue: SegmentIssue::AddressOverflow | SegmentIssue::AboveUserLimit,
+                ..
+            }) => AxError::InvalidInput,
+            ElfError::StackTooSmall => AxError::ArgumentListTooLong,
+            ElfError::NoAddressSpace => AxError::NoMemory,
+            _ => AxError::InvalidExecutable,
+        }
+    }
+}
+
+impl From<ElfError> for LinuxError {
+    fn from(err: ElfError) -> Self {
+        AxError::from(err).into()
+    }
+}
@@ -0,0 +1,156 @@
+//! Plan of loading a program together with its dynamic linker.
+extern crate alloc;
+use alloc::{collections::BTreeMap, vec::Vec};
+use memory_addr::VirtAddr;
+
+use crate::{
+    auxv::{AT_BASE, AT_ENTRY},
+    ELFSegment, ElfError, ElfImage, ElfResult, GnuProperties, LinkKind, StackInfo,
+};
+
+/// Builder of an [`ExecPlan`].
+///
+/// It takes the main program and, when the program is dynamically linked, the interpreter
+/// (e.g. `ld.so`) named by [`ElfImage::interp`].
+pub struct ExecPlanBuilder<'a, 'b> {
+    main: &'b ElfImage<'a>,
+    main_base: usize,
+    interp: Option<(&'b ElfImage<'a>, usize)>,
+    brk_offset: usize,
+    static_pie_brk: Option<usize>,
+}
+
+impl<'a, 'b> ExecPlanBuilder<'a, 'b> {
+    /// Start a plan for the main program. `given_base` is used only if the program is
+    /// position-independent, see [`crate::elf_base_addr`].
+    pub fn new(main: &'b ElfImage<'a>, given_base: usize) -> Self {
+        Self {
+            main,
+            main_base: given_base,
+            interp: None,
+            brk_offset: 0,
+            static_pie_brk: None,
+        }
+    }
+
+    /// Load `interp` as the interpreter of the main program. `given_base` is used only if the
+    /// interpreter is position-independent.
+    pub fn interpreter(mut self, interp: &'b ElfImage<'a>, given_base: usize) -> Self {
+        self.interp = Some((interp, given_base));
+        self
+    }
+
+    /// Add `offset` to the start of the program break, e.g. one picked by
+    /// [`crate::AslrPolicy::brk_offset`].
+    pub fn brk_offset(mut self, offset: usize) -> Self {
+        self.brk_offset = offset;
+        self
+    }
+
+    /// Start the program break of a static-pie main program at `addr` instead of the end of
+    /// the image, like Linux moves it to `ELF_ET_DYN_BASE` when randomizing, so the heap is
+    /// not limited by the mappings placed after the low-loaded image.
+    pub fn static_pie_brk(mut self, addr: usize) -> Self {
+        self.static_pie_brk = Some(addr);
+        self
+    }
+
+    /// Compute the segments, the entry and the auxiliary vectors.
+    ///
+    /// The interpreter must be built for the same machine and class as the main program.
+    pub fn build(self) -> ElfResult<ExecPlan<'a>> {
+        let main_base = self.main.base_addr(self.main_base)?;
+        let main_entry = self.main.entry(main_base)?;
+        let mut segments = self.main.segments(main_base)?;
+        // AT_PHDR, AT_PHNUM and AT_PHENT always describe the main program.
+        let mut auxv = self.main.auxv(main_base)?;
+        auxv.insert(AT_ENTRY, main_entry.as_usize());
+
+        let main_properties = self.main.gnu_properties()?;
+        let mut interp_properties = None;
+        #[cfg(feature = "aarch64")]
+        let main_count = segments.len();
+
+        let (entry, interp_base) = match self.interp {
+            Some((interp, given_base)) => {
+                if interp.machine() != self.main.machine() {
+                    return Err(ElfError::BadMachine(interp.machine().e_machine()));
+                }
+                if interp.class() != self.main.class() {
+                    return Err(ElfError::BadClass);
+                }
+                let interp_base = interp.base_addr(given_base)?;
+                interp_properties = Some(interp.gnu_properties()?);
+                segments.extend(interp.segments(interp_base)?);
+                (interp.entry(interp_base)?, Some(interp_base))
+            }
+            None => (main_entry, None),
+        };
+        auxv.insert(AT_BASE, interp_base.unwrap_or(0));
+
+        #[cfg(feature = "aarch64")]
+        if self.main.machine() == crate::arch::Machine::AArch64 {
+            use crate::arch::aarch64::{guard_segments, BranchProtection};
+            // Only the interpreter is guarded if there is one.
+            let (guarded, properties) = match &interp_properties {
+                Some(properties) => (&mut segments[main_count..], properties),
+                None => (&mut segments[..], &main_properties),
+            };
+            guard_segments(guarded, BranchProtection::from_properties(properties));
+        }
+
+        // The program break follows the main program, never the interpreter.
+        let brk_start = match self.static_pie_brk {
+            Some(addr) if self.main.link_kind()? == LinkKind::StaticPie => addr,
+            _ => self.main.brk_start(main_base)?,
+        };
+        let brk_start = brk_start
+            .checked_add(self.brk_offset)
+            .ok_or(ElfError::AddressOverflow)?;
+
+        Ok(ExecPlan {
+            segments,
+            entry,
+            main_base,
+            main_entry,
+            interp_base,
+            auxv,
+            brk_start,
+            stack: self.main.stack_info(),
+            main_properties,
+            interp_properties,
+        })
+    }
+}
+
+/// Everything needed to start a program: what to map, where to jump and the auxiliary
+/// vectors to push onto the user stack.
+pub struct ExecPlan<'a> {
+    /// The `LOAD` segments of the main program, followed by those of the interpreter
+    pub segments: Vec<ELFSegment<'a>>,
+    /// The address to jump to, which is the entry of the interpreter if there is one
+    pub entry: VirtAddr,
+    /// The base address of the main program
+    pub main_base: usize,
+    /// The entry of the main program
+    pub main_entry: VirtAddr,
+    /// The base address of the interpreter
+    pub interp_base: Option<usize>,
+    /// The auxiliary vectors with `AT_BASE` and `AT_ENTRY` filled in
+    pub auxv: BTreeMap<u8, usize>,
+    /// The start of the program break (`start_brk`)
+    pub brk_start: usize,
+    /// The user stack requested by the main program
+    pub stack: StackInfo,
+    /// The GNU properties of the main program
+    pub main_properties: GnuProperties,
+    /// The GNU properties of the interpreter
+    pub interp_properties: Option<GnuProperties>,
+}
+
+impl<'a> ExecPlan<'a> {
+    /// Start building a plan for the main program. See [`ExecPlanBuilder::new`].
+    pub fn builder<'b>(main: &'b ElfImage<'a>, given_base: usize) -> ExecPlanBuilder<'a, 'b> {
+        ExecPlanBuilder::new(main, given_base)
+    }
+}
@@ -0,0 +1,338 @@
+//! A validated ELF image, which is checked once before loading.
+extern crate alloc;
+use alloc::{borrow::Cow, collections::BTreeMap, vec::Vec};
+use core::ops::Range;
+use memory_addr::VirtAddr;
+use xmas_elf::{
+    header::{self, Class, Data, Version},
+    program::{Flags, Type},
+    ElfFile,
+};
+
+use crate::{
+    arch::{self, Machine, RelocTarget, RelocatePair, SymbolResolver},
+    interp, layout, note, relro, section, tls, user_stack, validate, ELFSegment, ElfError,
+    ElfLayout, ElfResult, GnuProperties, LinkKind, LoadConfig, Note, SegmentDiagnostic, StackInfo,
+    TlsTemplate,
+};
+
+/// The class of the ELF file, which decides the size of a word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfClass {
+    /// 32-bit ELF file
+    Elf32,
+    /// 64-bit ELF file
+    Elf64,
+}
+
+impl ElfClass {
+    /// Read the class of the elf file.
+    pub fn from_elf(elf: &ElfFile) -> ElfResult<Self> {
+        match elf.header.pt1.class() {
+            Class::ThirtyTwo => Ok(Self::Elf32),
+            Class::SixtyFour => Ok(Self::Elf64),
+            _ => Err(ElfError::BadClass),
+        }
+    }
+
+    /// The class of the running architecture.
+    pub const fn host() -> Self {
+        if cfg!(target_pointer_width = "32") {
+            Self::Elf32
+        } else {
+            Self::Elf64
+        }
+    }
+
+    /// Size of a word (pointer, `argv` entry or auxiliary vector field) in bytes.
+    pub const fn word_size(self) -> usize {
+        match self {
+            Self::Elf32 => 4,
+            Self::Elf64 => 8,
+        }
+    }
+}
+
+/// Segment type describing the permissions of the user stack.
+pub(crate) const PT_GNU_STACK: u32 = 0x6474_e551;
+
+/// Segment type holding the GNU property note.
+pub(crate) const PT_GNU_PROPERTY: u32 = 0x6474_e553;
+
+/// Infomation about a program header, which is read once when the image is parsed.
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramHeaderInfo {
+    /// Type of the segment
+    pub type_: Type,
+    /// Permission flags of the segment
+    pub flags: Flags,
+    /// Offset of the segment in the file
+    pub offset: u64,
+    /// Virtual address of the segment
+    pub vaddr: u64,
+    /// Size of the segment in the file
+    pub file_size: u64,
+    /// Size of the segment in the memory
+    pub mem_size: u64,
+    /// Alignment of the segment
+    pub align: u64,
+}
+
+/// Read the program headers of the elf file after checking the bounds of the table.
+pub(crate) fn program_headers(elf: &ElfFile) -> ElfResult<Vec<ProgramHeaderInfo>> {
+    crate::check_header(elf)?;
+    let mut headers = Vec::with_capacity(elf.header.pt2.ph_count() as usize);
+    for index in 0..elf.header.pt2.ph_count() {
+        let ph = elf
+            .program_header(index)
+            .map_err(|_| ElfError::OutOfBounds)?;
+        // Unknown segment types are ignored, as the kernel does.
+        let Ok(type_) = ph.get_type() else {
+            continue;
+        };
+        headers.push(ProgramHeaderInfo {
+            type_,
+            flags: ph.flags(),
+            offset: ph.offset(),
+            vaddr: ph.virtual_addr(),
+            file_size: ph.file_size(),
+            mem_size: ph.mem_size(),
+            align: ph.align(),
+        });
+    }
+    Ok(headers)
+}
+
+/// An ELF file which has been validated, and whose program headers have been read.
+///
+/// All the checks are done by [`ElfImage::parse`], so a bad `execve` target can be
+/// rejected before touching the address space.
+pub struct ElfImage<'a> {
+    elf: ElfFile<'a>,
+    class: ElfClass,
+    machine: Machine,
+    program_headers: Vec<ProgramHeaderInfo>,
+    config: LoadConfig,
+}
+
+impl<'a> ElfImage<'a> {
+    /// Parse and validate the ELF file.
+    ///
+    /// It checks the class, the data encoding, the version, the machine type against the
+    /// running architecture, the file type and the bounds of the header tables. If the running
+    /// architecture is not supported, any supported [`Machine`] is accepted.
+    ///
+    /// The data must be aligned to 8 bytes, as the headers are read in place.
+    pub fn parse(data: &'a [u8]) -> ElfResult<Self> {
+        Self::parse_with(data, Machine::host())
+    }
+
+    /// Parse and validate the ELF file built for `machine`, which may differ from the running
+    /// architecture. See [`ElfImage::parse`].
+    pub fn parse_for(data: &'a [u8], machine: Machine) -> ElfResult<Self> {
+        Self::parse_with(data, Some(machine))
+    }
+
+    fn parse_with(data: &'a [u8], expected: Option<Machine>) -> ElfResult<Self> {
+        if data.len() < 16 {
+            return Err(ElfError::OutOfBounds);
+        }
+        if data[..4] != header::MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if !(data.as_ptr() as usize).is_multiple_of(8) {
+            return Err(ElfError::Misaligned);
+        }
+        let elf = ElfFile::new(data).map_err(|_| match data[4] {
+            1 | 2 => ElfError::OutOfBounds,
+            _ => ElfError::BadClass,
+        })?;
+        let pt1 = elf.header.pt1;
+        let class = ElfClass::from_elf(&elf)?;
+        if pt1.data() != Data::LittleEndian {
+            return Err(ElfError::BadEncoding);
+        }
+        if pt1.version() != Version::Current || elf.header.pt2.version() != 1 {
+            return Err(ElfError::BadVersion);
+        }
+        let machine = Machine::from_elf(&elf)?;
+        if expected.is_some_and(|expected| expected != machine) {
+            return Err(ElfError::BadMachine(machine.e_machine()));
+        }
+        if !matches!(
+            elf.header.pt2.type_().as_type(),
+            header::Type::Executable | header::Type::SharedObject
+        ) {
+            return Err(ElfError::BadType);
+        }
+        let program_headers = program_headers(&elf)?;
+        // Look up a section to check the bounds of the section header table.
+        section::find_section(&elf, ".shstrtab")?;
+        Ok(Self {
+            elf,
+            class,
+            machine,
+            program_headers,
+            config: LoadConfig::default(),
+        })
+    }
+
+    /// Load the image with `config` instead of [`LoadConfig::default`].
+    pub fn with_config(mut self, config: LoadConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The config the image is loaded with.
+    pub fn config(&self) -> &LoadConfig {
+        &self.config
+    }
+
+    /// The underlying [`xmas_elf::ElfFile`].
+    pub fn elf(&self) -> &ElfFile<'a> {
+        &self.elf
+    }
+
+    /// The class of the image.
+    pub fn class(&self) -> ElfClass {
+        self.class
+    }
+
+    /// The machine the image is built for.
+    pub fn machine(&self) -> Machine {
+        self.machine
+    }
+
+    /// The program headers of the image.
+    pub fn program_headers(&self) -> &[ProgramHeaderInfo] {
+        &self.program_headers
+    }
+
+    /// The path of the dynamic linker requested by the image. See [`crate::elf_interp`].
+    pub fn interp(&self) -> ElfResult<Option<&'a str>> {
+        interp::interp_from(&self.elf, &self.program_headers)
+    }
+
+    /// How the image is linked. See [`crate::elf_link_kind`].
+    pub fn link_kind(&self) -> ElfResult<LinkKind> {
+        interp::link_kind_from(&self.elf, &self.program_headers)
+    }
+
+    /// The user stack requested by the image. See [`crate::elf_stack_info`].
+    pub fn stack_info(&self) -> StackInfo {
+        user_stack::stack_info_from(&self.program_headers)
+    }
+
+    /// Check the `LOAD` segments loaded at `base_addr`. See [`crate::validate_segments`].
+    pub fn validate(&self, base_addr: usize) -> Vec<SegmentDiagnostic> {
+        validate::validate_from(
+            self.elf.input.len(),
+            &self.program_headers,
+            base_addr,
+            &self.config,
+        )
+    }
+
+    /// The notes of the image. See [`crate::elf_notes`].
+    pub fn notes(&self) -> ElfResult<Vec<Note<'a>>> {
+        note::notes_from(&self.elf, &self.program_headers)
+    }
+
+    /// The GNU properties of the image. See [`crate::elf_gnu_properties`].
+    pub fn gnu_properties(&self) -> ElfResult<GnuProperties> {
+        note::gnu_properties_from(&self.elf, &self.program_headers, self.machine)
+    }
+
+    /// The TLS template of the image. See [`crate::elf_tls_template`].
+    pub fn tls_template(&self) -> ElfResult<Option<TlsTemplate<'a>>> {
+        tls::tls_template_from(&self.elf, &self.program_headers)
+    }
+
+    /// The address range of the `LOAD` segments. See [`crate::elf_layout`].
+    pub fn layout(&self) -> ElfResult<ElfLayout> {
+        layout::layout_from(&self.program_headers, &self.config)
+    }
+
+    /// The start of the program break of the image. See [`ElfLayout::brk_start`].
+    pub fn brk_start(&self, base_addr: usize) -> ElfResult<usize> {
+        self.layout()?.brk_start(base_addr)
+    }
+
+    /// Base address of the image loaded into the memory. See [`crate::elf_base_addr`].
+    pub fn base_addr(&self, given_base: usize) -> ElfResult<usize> {
+        crate::base_addr_from(&self.elf, &self.program_headers, given_base, &self.config)
+    }
+
+    /// Read all [`ELFSegment`] with `LOAD` type. See [`crate::elf_segments`].
+    pub fn segments(&self, base_addr: usize) -> ElfResult<Vec<ELFSegment<'a>>> {
+        crate::segments_from(
+            &self.elf,
+            &self.program_headers,
+            base_addr,
+            true,
+            &self.config,
+        )
+    }
+
+    /// Read all [`ELFSegment`] with `LOAD` type without their data. See
+    /// [`crate::elf_file_segments`].
+    pub fn file_segments(&self, base_addr: usize) -> ElfResult<Vec<ELFSegment<'a>>> {
+        crate::segments_from(
+            &self.elf,
+            &self.program_headers,
+            base_addr,
+            false,
+            &self.config,
+        )
+    }
+
+    /// The entry point of the image. See [`crate::elf_entry`].
+    pub fn entry(&self, base_addr: usize) -> ElfResult<VirtAddr> {
+        let entry = (self.elf.header.pt2.entry_point() as usize)
+            .checked_add(base_addr)
+            .ok_or(ElfError::AddressOverflow)?;
+        Ok(entry.into())
+    }
+
+    /// The auxiliary vectors of the image. See [`crate::auxv_vector`].
+    pub fn auxv(&self, base_addr: usize) -> ElfResult<BTreeMap<u8, usize>> {
+        crate::auxv::auxv_from(&self.elf, &self.program_headers, base_addr, &self.config)
+    }
+
+    /// The range to remap read-only after relocation. See [`crate::elf_relro`].
+    pub fn relro(&self, base_addr: usize) -> ElfResult<Option<Range<VirtAddr>>> {
+        relro::relro_from(&self.program_headers, base_addr, &self.config)
+    }
+
+    /// The relocate pairs of the image. See [`crate::arch::relocate_pairs_for`].
+    pub fn relocate_pairs(&self, base_addr: usize) -> ElfResult<Vec<RelocatePair>> {
+        self.relocate_pairs_with(base_addr, &arch::NoResolver)
+    }
+
+    /// The relocate pairs of the image, looking up the undefined symbols with `resolver`.
+    /// See [`crate::arch::relocate_pairs_with`].
+    pub fn relocate_pairs_with(
+        &self,
+        base_addr: usize,
+        resolver: &dyn SymbolResolver,
+    ) -> ElfResult<Vec<RelocatePair>> {
+        let context = self.reloc_context()?;
+        arch::dispatch(self.machine, &context, base_addr, resolver)
+    }
+
+    /// Apply the relocations of the image to `target`, looking up the undefined symbols with
+    /// `resolver`. See [`crate::arch::apply_relocations_with`].
+    pub fn apply_relocations(
+        &self,
+        base_addr: usize,
+        resolver: &dyn SymbolResolver,
+        target: &mut impl RelocTarget,
+    ) -> ElfResult {
+        let context = self.reloc_context()?;
+        arch::apply_from(self.machine, &context, base_addr, resolver, target)
+    }
+
+    fn reloc_context(&self) -> ElfResult<arch::RelocContext<'a, '_>> {
+        arch::RelocContext::with_program_headers(&self.elf, Cow::Borrowed(&self.program_headers))
+    }
+}
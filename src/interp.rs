@@ -0,0 +1,67 @@
+//! The dynamic linker requested by the ELF file.
+use xmas_elf::{header, program::Type};
+
+use crate::{image::program_headers, section, ElfError, ElfResult, ProgramHeaderInfo};
+
+/// Longest interpreter path accepted, including the NUL terminator (`PATH_MAX` on Linux).
+const PATH_MAX: u64 = 4096;
+
+/// How an ELF file is linked, which decides whether an interpreter must be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// A statically linked executable loaded at the addresses in the file.
+    Static,
+    /// A statically linked position-independent executable, which relocates itself.
+    StaticPie,
+    /// An executable which requests a dynamic linker with a `PT_INTERP` segment.
+    Dynamic,
+}
+
+/// Read the path of the dynamic linker in the `PT_INTERP` segment.
+///
+/// # Return
+///
+/// `None` if the file has no `PT_INTERP` segment. The path must be NUL-terminated,
+/// non-empty and shorter than `PATH_MAX`, otherwise [`ElfError::BadInterpreter`] is returned.
+pub fn elf_interp<'a>(elf: &xmas_elf::ElfFile<'a>) -> ElfResult<Option<&'a str>> {
+    interp_from(elf, &program_headers(elf)?)
+}
+
+/// Tell whether the ELF file is static, static-pie or dynamically linked.
+pub fn elf_link_kind(elf: &xmas_elf::ElfFile) -> ElfResult<LinkKind> {
+    link_kind_from(elf, &program_headers(elf)?)
+}
+
+pub(crate) fn interp_from<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+    program_headers: &[ProgramHeaderInfo],
+) -> ElfResult<Option<&'a str>> {
+    // Only the first PT_INTERP segment is used, as the kernel does.
+    let Some(ph) = program_headers.iter().find(|ph| ph.type_ == Type::Interp) else {
+        return Ok(None);
+    };
+    if ph.file_size < 2 || ph.file_size > PATH_MAX {
+        return Err(ElfError::BadInterpreter);
+    }
+    let data = section::file_range(elf, ph.offset, ph.file_size)?;
+    let (nul, path) = data.split_last().ok_or(ElfError::BadInterpreter)?;
+    if *nul != 0 || path.contains(&0) {
+        return Err(ElfError::BadInterpreter);
+    }
+    core::str::from_utf8(path)
+        .map(Some)
+        .map_err(|_| ElfError::BadInterpreter)
+}
+
+pub(crate) fn link_kind_from(
+    elf: &xmas_elf::ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+) -> ElfResult<LinkKind> {
+    if interp_from(elf, program_headers)?.is_some() {
+        Ok(LinkKind::Dynamic)
+    } else if elf.header.pt2.type_().as_type() == header::Type::SharedObject {
+        Ok(LinkKind::StaticPie)
+    } else {
+        Ok(LinkKind::Static)
+    }
+}
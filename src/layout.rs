@@ -0,0 +1,81 @@
+//! The address range occupied by the `LOAD` segments of an image.
+use xmas_elf::program::Type;
+
+use crate::{image::program_headers, ElfError, ElfResult, LoadConfig, ProgramHeaderInfo};
+
+/// The address range of the `LOAD` segments, which is reserved before mapping them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElfLayout {
+    /// The lowest virtual address of the segments, rounded down to the page size
+    pub min_vaddr: usize,
+    /// The highest virtual address of the segments, rounded up to the page size
+    pub max_vaddr: usize,
+    /// The largest `p_align` of the segments, which is at least the page size
+    pub max_align: usize,
+}
+
+impl ElfLayout {
+    /// Size of the address range occupied by the image.
+    pub fn span(&self) -> usize {
+        self.max_vaddr - self.min_vaddr
+    }
+
+    /// The value added to the virtual addresses in the file so that the lowest segment is
+    /// loaded at `base`.
+    pub fn load_bias(&self, base: usize) -> ElfResult<usize> {
+        base.checked_sub(self.min_vaddr)
+            .ok_or(ElfError::AddressOverflow)
+    }
+
+    /// The start of the program break (`start_brk`), which is the page-aligned end of the
+    /// highest segment loaded with `load_bias`, including its `.bss`.
+    pub fn brk_start(&self, load_bias: usize) -> ElfResult<usize> {
+        self.max_vaddr
+            .checked_add(load_bias)
+            .ok_or(ElfError::AddressOverflow)
+    }
+}
+
+/// Compute the [`ElfLayout`] of the `LOAD` segments with 4K pages.
+pub fn elf_layout(elf: &xmas_elf::ElfFile) -> ElfResult<ElfLayout> {
+    layout_from(&program_headers(elf)?, &LoadConfig::default())
+}
+
+/// The start of the program break of the elf file loaded at `base_addr`, which is the value
+/// returned by [`crate::elf_base_addr`]. See [`ElfLayout::brk_start`].
+pub fn elf_brk_start(elf: &xmas_elf::ElfFile, base_addr: usize) -> ElfResult<usize> {
+    elf_layout(elf)?.brk_start(base_addr)
+}
+
+pub(crate) fn layout_from(
+    program_headers: &[ProgramHeaderInfo],
+    config: &LoadConfig,
+) -> ElfResult<ElfLayout> {
+    let page_size = config.page_size();
+    let mut layout: Option<ElfLayout> = None;
+    for ph in program_headers.iter().filter(|ph| ph.type_ == Type::Load) {
+        let start = usize::try_from(ph.vaddr).map_err(|_| ElfError::AddressOverflow)?;
+        let end = usize::try_from(ph.mem_size)
+            .ok()
+            .and_then(|size| start.checked_add(size))
+            .and_then(|end| end.checked_next_multiple_of(page_size))
+            .ok_or(ElfError::AddressOverflow)?;
+        let start = start - start % page_size;
+        let align = usize::try_from(ph.align)
+            .unwrap_or(usize::MAX)
+            .max(page_size);
+        layout = Some(match layout {
+            Some(layout) => ElfLayout {
+                min_vaddr: layout.min_vaddr.min(start),
+                max_vaddr: layout.max_vaddr.max(end),
+                max_align: layout.max_align.max(align),
+            },
+            None => ElfLayout {
+                min_vaddr: start,
+                max_vaddr: end,
+                max_align: align,
+            },
+        });
+    }
+    layout.ok_or(ElfError::NoLoadSegment)
+}
@@ -3,36 +3,143 @@
 
 pub mod arch;
 extern crate alloc;
-use alloc::{
-    string::{String, ToString},
-    vec::Vec,
-};
+use alloc::vec::Vec;
+use core::{mem::size_of, ops::Range};
 use log::info;
-use memory_addr::{VirtAddr, PAGE_SIZE_4K};
+use memory_addr::VirtAddr;
 
 use page_table_entry::MappingFlags;
+use xmas_elf::{
+    header::{Class, HeaderPt2, MAGIC},
+    program::{ProgramHeader32, ProgramHeader64, Type},
+};
 
+mod aslr;
 mod auxv;
+mod config;
+mod dynamic;
+mod error;
+mod exec;
+mod image;
+mod interp;
+mod layout;
+pub mod note;
+mod relro;
+mod section;
+mod tls;
+mod validate;
+pub use aslr::{AslrPolicy, AslrRange, BaseKind, EntropySource, ADDR_NO_RANDOMIZE};
 pub use auxv::auxv_vector;
-pub use user_stack::app_stack_region;
+pub use config::LoadConfig;
+pub use error::{ElfError, ElfResult};
+pub use exec::{ExecPlan, ExecPlanBuilder};
+pub use image::{ElfClass, ElfImage, ProgramHeaderInfo};
+pub use interp::{elf_interp, elf_link_kind, LinkKind};
+pub use layout::{elf_brk_start, elf_layout, ElfLayout};
+pub use note::{elf_gnu_properties, elf_notes, GnuProperties, Note};
+pub use relro::elf_relro;
+pub use tls::{elf_tls_template, TlsBlock, TlsTemplate};
+pub use user_stack::{app_stack_region, app_stack_region_for, elf_stack_info, StackInfo};
+pub use validate::{validate_segments, SegmentDiagnostic, SegmentIssue};
 mod user_stack;
 
 /// Infomation about the elf segment, which is used to map the elf file to the memory space
-pub struct ELFSegment {
+///
+/// The data borrows the buffer of the ELF file, so it can be copied straight into the
+/// mapped user pages without an intermediate allocation.
+pub struct ELFSegment<'a> {
     /// The start [`VirtAddr`] of the segment
     pub vaddr: VirtAddr,
     /// Size of the segment
     pub size: usize,
+    /// Offset in the file of the data mapped at `vaddr`
+    pub offset: usize,
+    /// Size of the file-backed part starting at `vaddr`, which is the length of `data` if any
+    pub file_size: usize,
+    /// Size of the part after the file data which must be zeroed up to the end of the last
+    /// file-backed page (`padzero` in Linux)
+    pub zero_size: usize,
+    /// Size of the whole anonymous zero pages after the file-backed pages (`set_brk` in Linux)
+    pub anon_size: usize,
     /// [`MappingFlags`] of the segment which is used to set the page table entry
     pub flags: MappingFlags,
     /// The data of the segment
-    pub data: Option<Vec<u8>>,
+    pub data: Option<&'a [u8]>,
+    /// Size of the pages the segment is mapped with, see [`LoadConfig::page_size`]
+    pub page_size: usize,
+    /// The pages must be mapped as guarded pages (the `GP` bit of aarch64), which is set by
+    /// [`arch::aarch64::guard_segments`] for the executable segments of a BTI image
+    pub guarded: bool,
+}
+
+/// How the file-backed pages of an [`ELFSegment`] are shared with the backing file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentSharing {
+    /// The pages are never written, so the pages of the file (e.g. the page cache) can be
+    /// mapped read-only and shared by every process.
+    SharedReadOnly,
+    /// The pages may be written, either by the program or by zeroing the `.bss` part of the
+    /// last page, so each process needs its own copy-on-write copy of them.
+    PrivateCow,
+}
+
+impl ELFSegment<'_> {
+    /// Page-aligned range of the file offsets mapped at `vaddr`.
+    ///
+    /// The page fault handler can read the page at `vaddr + n * PAGE_SIZE` from the file
+    /// offset `file_pages().start + n * PAGE_SIZE`, like `elf_map` in Linux. The `zero_size`
+    /// bytes at the end of the last page must be zeroed after reading it.
+    pub fn file_pages(&self) -> Range<usize> {
+        self.offset..(self.offset + self.file_size).next_multiple_of(self.page_size)
+    }
+
+    /// How the file-backed pages are shared. Writable segments, and segments whose last file
+    /// page is partly zeroed, are [`SegmentSharing::PrivateCow`].
+    pub fn sharing(&self) -> SegmentSharing {
+        if self.flags.contains(MappingFlags::WRITE) || self.zero_size != 0 {
+            SegmentSharing::PrivateCow
+        } else {
+            SegmentSharing::SharedReadOnly
+        }
+    }
+}
+
+/// Check the ELF header and the bounds of the program header table.
+///
+/// [`xmas_elf`] panics when reading a program header outside the file, so it must be called
+/// before iterating the program headers.
+pub(crate) fn check_header(elf: &xmas_elf::ElfFile) -> ElfResult {
+    if elf.header.pt1.magic != MAGIC {
+        return Err(ElfError::BadMagic);
+    }
+    let (entry_size, align) = match (elf.header.pt1.class(), elf.header.pt2) {
+        (Class::ThirtyTwo, HeaderPt2::Header32(_)) => (size_of::<ProgramHeader32>(), 4),
+        (Class::SixtyFour, HeaderPt2::Header64(_)) => (size_of::<ProgramHeader64>(), 8),
+        _ => return Err(ElfError::BadClass),
+    };
+    let pt2 = &elf.header.pt2;
+    if pt2.ph_count() == 0 {
+        return Ok(());
+    }
+    if pt2.ph_entry_size() as usize != entry_size {
+        return Err(ElfError::BadClass);
+    }
+    let table = section::file_range(
+        elf,
+        pt2.ph_offset(),
+        pt2.ph_count() as u64 * entry_size as u64,
+    )?;
+    if !(table.as_ptr() as usize).is_multiple_of(align) {
+        return Err(ElfError::Misaligned);
+    }
+    Ok(())
 }
 
 /// Base address of the ELF file loaded into the memory.
 ///
 /// - When the ELF file is a **position-independent executable**,
-/// the base address will be decided by the kernel.
+///   the base address will be decided by the kernel. The lowest `LOAD` segment is loaded at
+///   `given_base`, and the returned value is the load bias (see [`ElfLayout::load_bias`]).
 ///
 /// - Otherwise, the base address **is determined by the file**, and this field `given_base` will be ignored.
 ///
@@ -45,27 +152,37 @@ pub struct ELFSegment {
 /// # Return
 ///
 /// The real base address for ELF file loaded into the memory.
-pub fn elf_base_addr(elf: &xmas_elf::ElfFile, given_base: usize) -> Result<usize, String> {
+pub fn elf_base_addr(elf: &xmas_elf::ElfFile, given_base: usize) -> ElfResult<usize> {
+    base_addr_from(
+        elf,
+        &image::program_headers(elf)?,
+        given_base,
+        &LoadConfig::default(),
+    )
+}
+
+pub(crate) fn base_addr_from(
+    elf: &xmas_elf::ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+    given_base: usize,
+    config: &LoadConfig,
+) -> ElfResult<usize> {
     // Some elf will load ELF Header (offset == 0) to vaddr 0. In that case, base_addr will be added to all the LOAD.
     if elf.header.pt2.type_().as_type() == xmas_elf::header::Type::Executable {
-        if let Some(ph) = elf
-            .program_iter()
-            .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
-        {
+        if let Some(ph) = program_headers.iter().find(|ph| ph.type_ == Type::Load) {
             // The LOAD segements are sorted by the virtual address, so the first one is the lowest one.
-            if ph.virtual_addr() == 0 {
-                Err(
-                    "The ELF file is an executable, but some segements may be loaded to vaddr 0"
-                        .to_string(),
-                )
+            if ph.vaddr == 0 {
+                Err(ElfError::BadSegment(
+                    "the ELF file is an executable, but some segements may be loaded to vaddr 0",
+                ))
             } else {
                 Ok(0)
             }
         } else {
-            Err("The ELF file is an executable, but no LOAD segment found".to_string())
+            Err(ElfError::NoLoadSegment)
         }
     } else {
-        Ok(given_base)
+        layout::layout_from(program_headers, config)?.load_bias(given_base)
     }
 }
 
@@ -78,48 +195,132 @@ pub fn elf_base_addr(elf: &xmas_elf::ElfFile, given_base: usize) -> Result<usize
 ///
 /// # Warning
 /// It can't be used to parse the elf file **which need the dynamic linker**, but you can do this **by calling this function recursively.**
-pub fn elf_segments(elf: &xmas_elf::ElfFile, base_addr: usize) -> Vec<ELFSegment> {
-    let elf_header = elf.header;
-    let magic = elf_header.pt1.magic;
-    assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
+/// The path of the dynamic linker is given by [`elf_interp`].
+pub fn elf_segments<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+    base_addr: usize,
+) -> ElfResult<Vec<ELFSegment<'a>>> {
+    segments_from(
+        elf,
+        &image::program_headers(elf)?,
+        base_addr,
+        true,
+        &LoadConfig::default(),
+    )
+}
+
+/// Read all [`self::ELFSegment`] with `LOAD` type of the elf file without their data, for
+/// loading the pages on demand from the file.
+///
+/// See [`ELFSegment::file_pages`] and [`ELFSegment::sharing`] for how to map the pages, and
+/// [`elf_segments`] for the arguments.
+pub fn elf_file_segments<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+    base_addr: usize,
+) -> ElfResult<Vec<ELFSegment<'a>>> {
+    segments_from(
+        elf,
+        &image::program_headers(elf)?,
+        base_addr,
+        false,
+        &LoadConfig::default(),
+    )
+}
 
+pub(crate) fn segments_from<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+    program_headers: &[ProgramHeaderInfo],
+    base_addr: usize,
+    with_data: bool,
+    config: &LoadConfig,
+) -> ElfResult<Vec<ELFSegment<'a>>> {
+    let page_size = config.page_size();
     info!("Base addr for the elf: 0x{:x}", base_addr);
+    let diagnostics = validate::validate_from(elf.input.len(), program_headers, base_addr, config);
+    if let Some(&diagnostic) = diagnostics.first() {
+        return Err(ElfError::InvalidSegment(diagnostic));
+    }
     let mut segments = Vec::new();
     // Load Elf "LOAD" segments at base_addr.
-    elf.program_iter()
-        .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
-        .for_each(|ph| {
-            let mut start_va = ph.virtual_addr() as usize + base_addr;
-            let end_va = (ph.virtual_addr() + ph.mem_size()) as usize + base_addr;
-            let mut start_offset = ph.offset() as usize;
-            let end_offset = (ph.offset() + ph.file_size()) as usize;
-
-            // Virtual address from elf may not be aligned.
-            assert_eq!(start_va % PAGE_SIZE_4K, start_offset % PAGE_SIZE_4K);
-            let front_pad = start_va % PAGE_SIZE_4K;
-            start_va -= front_pad;
-            start_offset -= front_pad;
-
-            let mut flags = MappingFlags::USER;
-            if ph.flags().is_read() {
-                flags |= MappingFlags::READ;
-            }
-            if ph.flags().is_write() {
-                flags |= MappingFlags::WRITE;
-            }
-            if ph.flags().is_execute() {
-                flags |= MappingFlags::EXECUTE;
-            }
-            let data = Some(elf.input[start_offset..end_offset].to_vec());
-            segments.push(ELFSegment {
-                vaddr: VirtAddr::from(start_va),
-                size: end_va - start_va,
-                flags,
-                data,
-            });
+    for ph in program_headers.iter().filter(|ph| ph.type_ == Type::Load) {
+        let mut start_va = (ph.vaddr as usize)
+            .checked_add(base_addr)
+            .ok_or(ElfError::AddressOverflow)?;
+        let end_va = start_va
+            .checked_add(ph.mem_size as usize)
+            .ok_or(ElfError::AddressOverflow)?;
+        let mut start_offset = ph.offset as usize;
+        section::file_range(elf, ph.offset, ph.file_size)?;
+        let end_offset = (ph.offset + ph.file_size) as usize;
+
+        // 0 and 1 mean no alignment is required, otherwise the segments must not share the
+        // pages of the given size.
+        if ph.align > 1 && !ph.align.is_power_of_two() {
+            return Err(ElfError::BadSegment("alignment is not a power of two"));
+        }
+        if ph.align > 1 && ph.align < page_size as u64 {
+            return Err(ElfError::BadSegment(
+                "alignment is smaller than the page size",
+            ));
+        }
+        // Virtual address from elf may not be aligned.
+        if start_va % page_size != start_offset % page_size {
+            return Err(ElfError::BadSegment(
+                "virtual address and file offset are not congruent modulo the page size",
+            ));
+        }
+        let front_pad = start_va % page_size;
+        start_va -= front_pad;
+        start_offset -= front_pad;
+
+        let mut flags = MappingFlags::USER;
+        if ph.flags.is_read() {
+            flags |= MappingFlags::READ;
+        }
+        if ph.flags.is_write() {
+            flags |= MappingFlags::WRITE;
+        }
+        if ph.flags.is_execute() {
+            flags |= MappingFlags::EXECUTE;
+        }
+        // A segment with no file data has no file-backed page, even if its start is not
+        // page-aligned.
+        let end_offset = if ph.file_size == 0 {
+            start_offset
+        } else {
+            end_offset
+        };
+        let data = with_data.then(|| &elf.input[start_offset..end_offset]);
+        let file_size = end_offset - start_offset;
+        // Page-aligned end of the memory image.
+        let mem_end = end_va
+            .checked_next_multiple_of(page_size)
+            .ok_or(ElfError::AddressOverflow)?;
+        let (zero_size, anon_size) = if ph.mem_size <= ph.file_size {
+            (0, 0)
+        } else if ph.file_size == 0 {
+            // Nothing is read from the file, so the whole range is anonymous.
+            (0, mem_end - start_va)
+        } else {
+            let file_end = start_va + file_size;
+            let file_page_end = file_end.next_multiple_of(page_size);
+            (file_page_end - file_end, mem_end - file_page_end)
+        };
+        segments.push(ELFSegment {
+            vaddr: VirtAddr::from(start_va),
+            size: end_va - start_va,
+            offset: start_offset,
+            file_size,
+            zero_size,
+            anon_size,
+            flags,
+            data,
+            page_size,
+            guarded: false,
         });
+    }
 
-    segments
+    Ok(segments)
 }
 
 /// Read the entry point of the elf file
@@ -131,13 +332,14 @@ pub fn elf_segments(elf: &xmas_elf::ElfFile, base_addr: usize) -> Vec<ELFSegment
 ///
 /// # Warning
 /// It can't be used to parse the elf file which need the dynamic linker, but you can do this by calling this function recursively
-pub fn elf_entry(elf: &xmas_elf::ElfFile, base_addr: usize) -> VirtAddr {
-    let elf_header = elf.header;
-    let magic = elf_header.pt1.magic;
-    assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
+/// The path of the dynamic linker is given by [`elf_interp`].
+pub fn elf_entry(elf: &xmas_elf::ElfFile, base_addr: usize) -> ElfResult<VirtAddr> {
+    check_header(elf)?;
 
     info!("Base addr for the elf: 0x{:x}", base_addr);
 
-    let entry = elf.header.pt2.entry_point() as usize + base_addr;
-    entry.into()
+    let entry = (elf.header.pt2.entry_point() as usize)
+        .checked_add(base_addr)
+        .ok_or(ElfError::AddressOverflow)?;
+    Ok(entry.into())
 }
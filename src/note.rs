@@ -0,0 +1,299 @@
+//! Notes in the `PT_NOTE` segments and the `SHT_NOTE` sections.
+//!
+//! The format of the GNU notes is described in
+//! <https://www.sco.com/developers/gabi/latest/ch5.pheader.html#note_section> and
+//! <https://gitlab.com/x86-psABIs/Linux-ABI>.
+extern crate alloc;
+use alloc::vec::Vec;
+use xmas_elf::{program::Type, sections::ShType};
+
+use crate::{
+    arch::Machine,
+    image::{program_headers, PT_GNU_PROPERTY},
+    section, ElfError, ElfResult, ProgramHeaderInfo,
+};
+
+/// Note type of the ABI tag.
+pub const NT_GNU_ABI_TAG: u32 = 1;
+/// Note type of the build ID.
+pub const NT_GNU_BUILD_ID: u32 = 3;
+/// Note type of the GNU properties.
+pub const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+
+/// Property of the aarch64 features the file is compatible with.
+pub const GNU_PROPERTY_AARCH64_FEATURE_1_AND: u32 = 0xc000_0000;
+/// Property of the x86 features the file is compatible with.
+pub const GNU_PROPERTY_X86_FEATURE_1_AND: u32 = 0xc000_0002;
+/// Property of the x86 ISA level the file needs.
+pub const GNU_PROPERTY_X86_ISA_1_NEEDED: u32 = 0xc000_8002;
+
+/// The file is compatible with Branch Target Identification.
+pub const GNU_PROPERTY_AARCH64_FEATURE_1_BTI: u32 = 1 << 0;
+/// The file is compatible with Pointer Authentication.
+pub const GNU_PROPERTY_AARCH64_FEATURE_1_PAC: u32 = 1 << 1;
+/// The file is compatible with Indirect Branch Tracking.
+pub const GNU_PROPERTY_X86_FEATURE_1_IBT: u32 = 1 << 0;
+/// The file is compatible with Shadow Stack.
+pub const GNU_PROPERTY_X86_FEATURE_1_SHSTK: u32 = 1 << 1;
+
+/// A note of the ELF file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note<'a> {
+    /// The owner of the note without the NUL terminator, e.g. `b"GNU"`
+    pub name: &'a [u8],
+    /// Type of the note, whose meaning depends on the owner
+    pub ty: u32,
+    /// The descriptor of the note
+    pub desc: &'a [u8],
+    /// Alignment of the note, which is 8 for the GNU properties of 64-bit files and 4
+    /// otherwise
+    pub align: usize,
+}
+
+/// The ABI tag, which tells the minimum kernel version the file runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbiTag {
+    /// The operating system, which is 0 for Linux
+    pub os: u32,
+    /// The major version of the kernel
+    pub major: u32,
+    /// The minor version of the kernel
+    pub minor: u32,
+    /// The patch version of the kernel
+    pub patch: u32,
+}
+
+/// A GNU property in a [`NT_GNU_PROPERTY_TYPE_0`] note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GnuProperty<'a> {
+    /// Type of the property
+    pub ty: u32,
+    /// Data of the property
+    pub data: &'a [u8],
+}
+
+/// The hardware features requested by the GNU properties of the ELF file.
+///
+/// A property which is absent is 0, so the file is treated as incompatible with the feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GnuProperties {
+    /// [`GNU_PROPERTY_X86_FEATURE_1_AND`], e.g. [`GNU_PROPERTY_X86_FEATURE_1_IBT`]
+    pub x86_features: u32,
+    /// [`GNU_PROPERTY_X86_ISA_1_NEEDED`], whose bits are the ISA levels (1 for the baseline,
+    /// 2 for v2, 4 for v3 and 8 for v4)
+    pub x86_isa_needed: u32,
+    /// [`GNU_PROPERTY_AARCH64_FEATURE_1_AND`], e.g. [`GNU_PROPERTY_AARCH64_FEATURE_1_BTI`]
+    pub aarch64_features: u32,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Iterator over the notes in a note segment or section.
+pub struct NoteIter<'a> {
+    data: &'a [u8],
+    align: usize,
+}
+
+impl<'a> NoteIter<'a> {
+    /// Iterate the notes in `data`, which are aligned to `align` bytes.
+    pub fn new(data: &'a [u8], align: usize) -> Self {
+        // Only 4 and 8 are used, other alignments are treated as 4, as Linux does.
+        let align = if align == 8 { 8 } else { 4 };
+        Self { data, align }
+    }
+
+    fn parse(&self) -> Option<(Note<'a>, usize)> {
+        let name_size = read_u32(self.data, 0)? as usize;
+        let desc_size = read_u32(self.data, 4)? as usize;
+        let ty = read_u32(self.data, 8)?;
+        let name_end = 12usize.checked_add(name_size)?;
+        let name = self.data.get(12..name_end)?;
+        let desc_start = name_end.checked_next_multiple_of(self.align)?;
+        let desc_end = desc_start.checked_add(desc_size)?;
+        let desc = self.data.get(desc_start..desc_end)?;
+        // The name is NUL-terminated, but the terminator is not part of it.
+        let name = name.strip_suffix(b"\0").unwrap_or(name);
+        let next = desc_end
+            .checked_next_multiple_of(self.align)?
+            .min(self.data.len());
+        Some((
+            Note {
+                name,
+                ty,
+                desc,
+                align: self.align,
+            },
+            next,
+        ))
+    }
+}
+
+impl<'a> Iterator for NoteIter<'a> {
+    type Item = ElfResult<Note<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        match self.parse() {
+            Some((note, next)) => {
+                self.data = &self.data[next..];
+                Some(Ok(note))
+            }
+            None => {
+                self.data = &[];
+                Some(Err(ElfError::MalformedSection("note")))
+            }
+        }
+    }
+}
+
+impl<'a> Note<'a> {
+    fn is_gnu(&self, ty: u32) -> bool {
+        self.name == b"GNU" && self.ty == ty
+    }
+
+    /// The build ID, if it is a [`NT_GNU_BUILD_ID`] note.
+    pub fn build_id(&self) -> Option<&'a [u8]> {
+        self.is_gnu(NT_GNU_BUILD_ID).then_some(self.desc)
+    }
+
+    /// The ABI tag, if it is a [`NT_GNU_ABI_TAG`] note.
+    pub fn abi_tag(&self) -> Option<AbiTag> {
+        if !self.is_gnu(NT_GNU_ABI_TAG) {
+            return None;
+        }
+        Some(AbiTag {
+            os: read_u32(self.desc, 0)?,
+            major: read_u32(self.desc, 4)?,
+            minor: read_u32(self.desc, 8)?,
+            patch: read_u32(self.desc, 12)?,
+        })
+    }
+
+    /// The properties, if it is a [`NT_GNU_PROPERTY_TYPE_0`] note.
+    pub fn gnu_properties(&self) -> Option<GnuPropertyIter<'a>> {
+        self.is_gnu(NT_GNU_PROPERTY_TYPE_0)
+            .then_some(GnuPropertyIter {
+                data: self.desc,
+                align: self.align,
+            })
+    }
+}
+
+/// Iterator over the properties in a [`NT_GNU_PROPERTY_TYPE_0`] note.
+pub struct GnuPropertyIter<'a> {
+    data: &'a [u8],
+    align: usize,
+}
+
+impl<'a> Iterator for GnuPropertyIter<'a> {
+    type Item = ElfResult<GnuProperty<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let property = (|| {
+            let ty = read_u32(self.data, 0)?;
+            let size = read_u32(self.data, 4)? as usize;
+            let end = 8usize.checked_add(size)?;
+            let data = self.data.get(8..end)?;
+            let next = end
+                .checked_next_multiple_of(self.align)?
+                .min(self.data.len());
+            Some((GnuProperty { ty, data }, next))
+        })();
+        match property {
+            Some((property, next)) => {
+                self.data = &self.data[next..];
+                Some(Ok(property))
+            }
+            None => {
+                self.data = &[];
+                Some(Err(ElfError::MalformedSection("GNU property note")))
+            }
+        }
+    }
+}
+
+/// Read all the notes of the elf file.
+///
+/// The notes are read from the `PT_NOTE` segments, or from the `SHT_NOTE` sections if the
+/// file has no such segment.
+pub fn elf_notes<'a>(elf: &xmas_elf::ElfFile<'a>) -> ElfResult<Vec<Note<'a>>> {
+    notes_from(elf, &program_headers(elf)?)
+}
+
+/// Read the GNU properties of the elf file, which are decoded for its machine.
+///
+/// The properties are read from the `PT_GNU_PROPERTY` segment only, as Linux does at exec
+/// time. A file without the segment has no properties; its notes can still be inspected with
+/// [`elf_notes`] and [`Note::gnu_properties`].
+pub fn elf_gnu_properties(elf: &xmas_elf::ElfFile) -> ElfResult<GnuProperties> {
+    gnu_properties_from(elf, &program_headers(elf)?, Machine::from_elf(elf)?)
+}
+
+pub(crate) fn notes_from<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+    program_headers: &[ProgramHeaderInfo],
+) -> ElfResult<Vec<Note<'a>>> {
+    let mut notes = Vec::new();
+    let segments = program_headers.iter().filter(|ph| ph.type_ == Type::Note);
+    if segments.clone().next().is_some() {
+        for ph in segments {
+            let data = section::file_range(elf, ph.offset, ph.file_size)?;
+            for note in NoteIter::new(data, ph.align as usize) {
+                notes.push(note?);
+            }
+        }
+    } else {
+        for sh in section::sections_of_type(elf, ShType::Note)? {
+            let data = section::file_range(elf, sh.offset(), sh.size())?;
+            for note in NoteIter::new(data, sh.align() as usize) {
+                notes.push(note?);
+            }
+        }
+    }
+    Ok(notes)
+}
+
+pub(crate) fn gnu_properties_from(
+    elf: &xmas_elf::ElfFile,
+    program_headers: &[ProgramHeaderInfo],
+    machine: Machine,
+) -> ElfResult<GnuProperties> {
+    let Some(ph) = program_headers
+        .iter()
+        .find(|ph| ph.type_ == Type::OsSpecific(PT_GNU_PROPERTY))
+    else {
+        return Ok(GnuProperties::default());
+    };
+    let data = section::file_range(elf, ph.offset, ph.file_size)?;
+    let notes = NoteIter::new(data, ph.align as usize).collect::<ElfResult<Vec<_>>>()?;
+    let mut properties = GnuProperties::default();
+    for property in notes.iter().filter_map(Note::gnu_properties).flatten() {
+        let property = property?;
+        // Other properties, e.g. the ones without data, are skipped whatever their size.
+        let field = match (machine, property.ty) {
+            (Machine::X86_64 | Machine::X86, GNU_PROPERTY_X86_FEATURE_1_AND) => {
+                &mut properties.x86_features
+            }
+            (Machine::X86_64 | Machine::X86, GNU_PROPERTY_X86_ISA_1_NEEDED) => {
+                &mut properties.x86_isa_needed
+            }
+            (Machine::AArch64, GNU_PROPERTY_AARCH64_FEATURE_1_AND) => {
+                &mut properties.aarch64_features
+            }
+            _ => continue,
+        };
+        if property.data.len() != 4 {
+            return Err(ElfError::MalformedSection("GNU property note"));
+        }
+        *field = read_u32(property.data, 0).unwrap();
+    }
+    Ok(properties)
+}
@@ -0,0 +1,43 @@
+//! The range made read-only after relocation (`PT_GNU_RELRO`).
+use core::ops::Range;
+
+use memory_addr::VirtAddr;
+use xmas_elf::program::Type;
+
+use crate::{image::program_headers, ElfError, ElfResult, LoadConfig, ProgramHeaderInfo};
+
+/// Read the range which should be remapped read-only after the relocations are applied.
+///
+/// # Arguments
+///
+/// * `elf` - The elf file
+/// * `base_addr` - The base address of the elf file if the file will be loaded to the memory
+///
+/// # Return
+///
+/// The page-aligned range described by the `PT_GNU_RELRO` segment, or `None` if the file
+/// has no such segment or the range does not cover a whole page. Like the dynamic linker,
+/// the end is rounded down so the page shared with writable data stays writable.
+pub fn elf_relro(elf: &xmas_elf::ElfFile, base_addr: usize) -> ElfResult<Option<Range<VirtAddr>>> {
+    relro_from(&program_headers(elf)?, base_addr, &LoadConfig::default())
+}
+
+pub(crate) fn relro_from(
+    program_headers: &[ProgramHeaderInfo],
+    base_addr: usize,
+    config: &LoadConfig,
+) -> ElfResult<Option<Range<VirtAddr>>> {
+    let Some(ph) = program_headers.iter().find(|ph| ph.type_ == Type::GnuRelro) else {
+        return Ok(None);
+    };
+    let start = (ph.vaddr as usize)
+        .checked_add(base_addr)
+        .ok_or(ElfError::AddressOverflow)?;
+    let end = start
+        .checked_add(ph.mem_size as usize)
+        .ok_or(ElfError::AddressOverflow)?;
+    let page_size = config.page_size();
+    let start = start - start % page_size;
+    let end = end - end % page_size;
+    Ok((start < end).then(|| VirtAddr::from(start)..VirtAddr::from(end)))
+}
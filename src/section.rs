@@ -0,0 +1,108 @@
+//! Bounds-checked access to the sections of the ELF file.
+//!
+//! The accessors of [`xmas_elf`] panic on truncated or misaligned data, so every
+//! section is checked here before its data is handed to them.
+extern crate alloc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use xmas_elf::{
+    header::Class,
+    sections::{SectionHeader, SectionHeader_, ShType, SHN_LORESERVE},
+    ElfFile, P32, P64,
+};
+
+use crate::{ElfError, ElfResult};
+
+/// Read a NUL-terminated UTF-8 string starting at `offset` of `data`.
+pub(crate) fn read_cstr(data: &[u8], offset: usize) -> Option<&str> {
+    let data = data.get(offset..)?;
+    let len = data.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&data[..len]).ok()
+}
+
+/// Get `size` bytes of the file starting at `offset`, checking the bounds.
+pub(crate) fn file_range<'a>(elf: &ElfFile<'a>, offset: u64, size: u64) -> ElfResult<&'a [u8]> {
+    let end = offset.checked_add(size).ok_or(ElfError::OutOfBounds)?;
+    if end > elf.input.len() as u64 {
+        return Err(ElfError::OutOfBounds);
+    }
+    Ok(&elf.input[offset as usize..end as usize])
+}
+
+fn check_section_table(elf: &ElfFile) -> ElfResult {
+    let pt2 = &elf.header.pt2;
+    let (entry_size, align) = match elf.header.pt1.class() {
+        Class::ThirtyTwo => (size_of::<SectionHeader_<P32>>(), 4),
+        Class::SixtyFour => (size_of::<SectionHeader_<P64>>(), 8),
+        _ => return Err(ElfError::BadClass),
+    };
+    let count = pt2.sh_count();
+    if count == 0 {
+        return Ok(());
+    }
+    if count >= SHN_LORESERVE || pt2.sh_str_index() >= count {
+        return Err(ElfError::MalformedSection("section header table"));
+    }
+    if pt2.sh_entry_size() as usize != entry_size {
+        return Err(ElfError::MalformedSection("section header table"));
+    }
+    let table = file_range(elf, pt2.sh_offset(), count as u64 * entry_size as u64)?;
+    if !(table.as_ptr() as usize).is_multiple_of(align) {
+        return Err(ElfError::Misaligned);
+    }
+    Ok(())
+}
+
+/// Find the section whose name is `name`.
+pub(crate) fn find_section<'a>(
+    elf: &ElfFile<'a>,
+    name: &str,
+) -> ElfResult<Option<SectionHeader<'a>>> {
+    check_section_table(elf)?;
+    let count = elf.header.pt2.sh_count();
+    if count == 0 {
+        return Ok(None);
+    }
+    let shstr = elf
+        .section_header(elf.header.pt2.sh_str_index())
+        .map_err(|_| ElfError::MalformedSection(".shstrtab"))?;
+    let shstr = file_range(elf, shstr.offset(), shstr.size())?;
+    for index in 0..count {
+        let section = elf
+            .section_header(index)
+            .map_err(|_| ElfError::MalformedSection("section header table"))?;
+        if section.get_type() == Ok(ShType::Null) {
+            continue;
+        }
+        if read_cstr(shstr, section.name() as usize) == Some(name) {
+            return Ok(Some(section));
+        }
+    }
+    Ok(None)
+}
+
+/// Find the sections whose type is `ty`.
+pub(crate) fn sections_of_type<'a>(
+    elf: &ElfFile<'a>,
+    ty: ShType,
+) -> ElfResult<Vec<SectionHeader<'a>>> {
+    check_section_table(elf)?;
+    let mut sections = Vec::new();
+    for index in 0..elf.header.pt2.sh_count() {
+        let section = elf
+            .section_header(index)
+            .map_err(|_| ElfError::MalformedSection("section header table"))?;
+        if section.get_type() == Ok(ty) {
+            sections.push(section);
+        }
+    }
+    Ok(sections)
+}
+
+/// Get the name of a dynamic symbol from the `.dynstr` section.
+pub(crate) fn dyn_symbol_name<'a>(elf: &ElfFile<'a>, name: u32) -> ElfResult<&'a str> {
+    let dynstr = find_section(elf, ".dynstr")?.ok_or(ElfError::MalformedSection(".dynstr"))?;
+    let data = file_range(elf, dynstr.offset(), dynstr.size())?;
+    read_cstr(data, name as usize).ok_or(ElfError::MalformedSection(".dynstr"))
+}
@@ -0,0 +1,137 @@
+//! Thread-local storage of static executables (`PT_TLS`).
+//!
+//! The layouts follow "ELF Handling For Thread-Local Storage":
+//! - Variant I (aarch64, arm, riscv): the thread pointer points to the TCB, which is followed
+//!   by the TLS block. The TCB is 16 bytes on aarch64, 8 bytes on arm and empty on riscv.
+//! - Variant II (x86_64, x86): the TLS block ends at the thread pointer, where the TCB starts
+//!   with a pointer to itself.
+extern crate alloc;
+use alloc::vec::Vec;
+use xmas_elf::program::Type;
+
+use crate::{
+    arch::Machine, image::program_headers, section, ElfClass, ElfError, ElfResult,
+    ProgramHeaderInfo,
+};
+
+/// The initialization image of the thread-local storage in the `PT_TLS` segment.
+#[derive(Debug, Clone, Copy)]
+pub struct TlsTemplate<'a> {
+    /// The `.tdata` part which initializes the TLS block
+    pub init: &'a [u8],
+    /// Size of the TLS block, including the zero-initialized `.tbss` part
+    pub mem_size: usize,
+    /// Alignment of the TLS block, which is a power of two
+    pub align: usize,
+}
+
+/// The initial TLS block of the main thread, built by [`TlsTemplate::build`].
+pub struct TlsBlock {
+    /// The data to copy to the address given to [`TlsTemplate::build`]
+    pub data: Vec<u8>,
+    /// The value of the thread pointer (`fs`, `tpidr_el0`, `tp`, ...)
+    pub thread_pointer: usize,
+}
+
+/// Read the TLS template from the `PT_TLS` segment.
+///
+/// # Return
+///
+/// `None` if the file has no `PT_TLS` segment.
+pub fn elf_tls_template<'a>(elf: &xmas_elf::ElfFile<'a>) -> ElfResult<Option<TlsTemplate<'a>>> {
+    tls_template_from(elf, &program_headers(elf)?)
+}
+
+pub(crate) fn tls_template_from<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+    program_headers: &[ProgramHeaderInfo],
+) -> ElfResult<Option<TlsTemplate<'a>>> {
+    let Some(ph) = program_headers.iter().find(|ph| ph.type_ == Type::Tls) else {
+        return Ok(None);
+    };
+    if ph.file_size > ph.mem_size {
+        return Err(ElfError::BadTls("file size exceeds memory size"));
+    }
+    // 0 and 1 mean no alignment is required.
+    let align = ph.align.max(1);
+    if !align.is_power_of_two() {
+        return Err(ElfError::BadTls("alignment is not a power of two"));
+    }
+    Ok(Some(TlsTemplate {
+        init: section::file_range(elf, ph.offset, ph.file_size)?,
+        mem_size: usize::try_from(ph.mem_size).map_err(|_| ElfError::AddressOverflow)?,
+        align: usize::try_from(align).map_err(|_| ElfError::AddressOverflow)?,
+    }))
+}
+
+impl TlsTemplate<'_> {
+    /// Build the initial TLS block of the main thread for `machine`, which will be copied to
+    /// `block_addr`.
+    ///
+    /// Only the TCB fields required by the ABI are filled in (the self pointer of variant II),
+    /// the rest of the thread descriptor is left to the C library.
+    ///
+    /// It fails with [`ElfError::BadTls`] if `init` is larger than `mem_size`, or if the block
+    /// can not be allocated.
+    pub fn build(&self, machine: Machine, block_addr: usize) -> ElfResult<TlsBlock> {
+        if self.init.len() > self.mem_size {
+            return Err(ElfError::BadTls("file size exceeds memory size"));
+        }
+        let word = match machine {
+            Machine::X86_64 | Machine::AArch64 => ElfClass::Elf64.word_size(),
+            Machine::X86 | Machine::Arm => ElfClass::Elf32.word_size(),
+            // The TCB of riscv is empty, so the word size only affects the alignment.
+            Machine::RiscV => ElfClass::host().word_size(),
+        };
+        let align = self.align.max(word);
+        let tls_size = self
+            .mem_size
+            .checked_next_multiple_of(align)
+            .ok_or(ElfError::AddressOverflow)?;
+        let overflow = || ElfError::AddressOverflow;
+        let (tls_addr, thread_pointer, end) = match machine {
+            Machine::X86_64 | Machine::X86 => {
+                let thread_pointer = block_addr
+                    .checked_add(tls_size)
+                    .and_then(|addr| addr.checked_next_multiple_of(align))
+                    .ok_or_else(overflow)?;
+                let end = thread_pointer.checked_add(word).ok_or_else(overflow)?;
+                (thread_pointer - tls_size, thread_pointer, end)
+            }
+            Machine::AArch64 | Machine::Arm | Machine::RiscV => {
+                let tcb_size: usize = match machine {
+                    Machine::AArch64 => 16,
+                    Machine::Arm => 8,
+                    _ => 0,
+                };
+                let thread_pointer = block_addr
+                    .checked_next_multiple_of(align)
+                    .ok_or_else(overflow)?;
+                let tls_addr = tcb_size
+                    .checked_next_multiple_of(align)
+                    .and_then(|offset| offset.checked_add(thread_pointer))
+                    .ok_or_else(overflow)?;
+                let end = tls_addr.checked_add(self.mem_size).ok_or_else(overflow)?;
+                (tls_addr, thread_pointer, end)
+            }
+        };
+
+        // The size comes from the file, so a failed allocation must not abort.
+        let mut data = Vec::new();
+        data.try_reserve_exact(end - block_addr)
+            .map_err(|_| ElfError::BadTls("TLS block is too large"))?;
+        data.resize(end - block_addr, 0);
+        let tls_offset = tls_addr - block_addr;
+        data[tls_offset..tls_offset + self.init.len()].copy_from_slice(self.init);
+        if matches!(machine, Machine::X86_64 | Machine::X86) {
+            // The TCB starts with a pointer to itself.
+            let tp_offset = thread_pointer - block_addr;
+            data[tp_offset..tp_offset + word]
+                .copy_from_slice(&thread_pointer.to_le_bytes()[..word]);
+        }
+        Ok(TlsBlock {
+            data,
+            thread_pointer,
+        })
+    }
+}
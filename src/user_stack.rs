@@ -31,17 +31,73 @@ extern crate alloc;
 
 use alloc::{collections::BTreeMap, string::String, vec::Vec};
 use memory_addr::VirtAddr;
+use page_table_entry::MappingFlags;
+use xmas_elf::program::Type;
+
+use crate::{
+    image::{program_headers, PT_GNU_STACK},
+    ElfClass, ElfError, ElfResult, ProgramHeaderInfo,
+};
+
+/// The user stack requested by the `PT_GNU_STACK` segment of the ELF file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackInfo {
+    /// [`MappingFlags`] the user stack should be mapped with
+    pub flags: MappingFlags,
+    /// The stack size requested by `p_memsz`, which is `None` if it is 0
+    pub size_hint: Option<usize>,
+    /// The file has no `PT_GNU_STACK` segment. Linux gives such 32-bit images
+    /// `READ_IMPLIES_EXEC`, which is left to the caller
+    pub legacy: bool,
+}
+
+/// Read the permissions and the size of the user stack from the `PT_GNU_STACK` segment.
+///
+/// A file without the segment is a legacy file, whose stack is readable and writable. Since
+/// Linux 5.8 only some 32-bit images get an executable stack through `READ_IMPLIES_EXEC`; the
+/// caller may add [`MappingFlags::EXECUTE`] when [`StackInfo::legacy`] is set.
+pub fn elf_stack_info(elf: &xmas_elf::ElfFile) -> ElfResult<StackInfo> {
+    Ok(stack_info_from(&program_headers(elf)?))
+}
+
+pub(crate) fn stack_info_from(program_headers: &[ProgramHeaderInfo]) -> StackInfo {
+    let rw = MappingFlags::USER | MappingFlags::READ | MappingFlags::WRITE;
+    match program_headers
+        .iter()
+        .find(|ph| ph.type_ == Type::OsSpecific(PT_GNU_STACK))
+    {
+        Some(ph) => StackInfo {
+            flags: if ph.flags.is_execute() {
+                rw | MappingFlags::EXECUTE
+            } else {
+                rw
+            },
+            size_hint: (ph.mem_size != 0).then_some(ph.mem_size as usize),
+            legacy: false,
+        },
+        None => StackInfo {
+            flags: rw,
+            size_hint: None,
+            legacy: true,
+        },
+    }
+}
 
 struct UserStack {
     sp: usize,
+    /// Size of the pointers and auxiliary vector fields pushed to the stack
+    word_size: usize,
 }
 
 impl UserStack {
-    pub fn new(sp: usize) -> Self {
-        Self { sp }
+    pub fn new(sp: usize, class: ElfClass) -> Self {
+        Self {
+            sp,
+            word_size: class.word_size(),
+        }
     }
     fn push(&mut self, src: &[u8], stack_data: &mut Vec<u8>) {
-        self.sp -= src.len();
+        self.sp = self.sp.wrapping_sub(src.len());
         // let mut target_data = src.to_vec();
         // target_data.append(stack_data);
         // *stack_data = target_data;
@@ -50,11 +106,11 @@ impl UserStack {
     pub fn push_usize_slice(&mut self, src: &[usize], stack_data: &mut Vec<u8>) {
         for val in src.iter().rev() {
             let bytes = val.to_le_bytes();
-            self.push(&bytes, stack_data);
+            self.push(&bytes[..self.word_size], stack_data);
         }
     }
     pub fn push_str(&mut self, str: &str, stack_data: &mut Vec<u8>) -> usize {
-        self.push(&['\0' as u8], stack_data);
+        self.push(b"\0", stack_data);
 
         self.push(str.as_bytes(), stack_data);
         self.sp
@@ -64,9 +120,15 @@ impl UserStack {
     }
 }
 
-fn init_stack(args: &[String], envs: &[String], auxv: &BTreeMap<u8, usize>, sp: usize) -> Vec<u8> {
+fn init_stack(
+    class: ElfClass,
+    args: &[String],
+    envs: &[String],
+    auxv: &BTreeMap<u8, usize>,
+    sp: usize,
+) -> Vec<u8> {
     let mut data = Vec::new();
-    let mut stack = UserStack::new(sp);
+    let mut stack = UserStack::new(sp, class);
     // define a random string with 16 bytes
     stack.push("0123456789abcdef".as_bytes(), &mut data);
     let random_str_pos = stack.get_sp();
@@ -79,11 +141,11 @@ fn init_stack(args: &[String], envs: &[String], auxv: &BTreeMap<u8, usize>, sp:
         .iter()
         .map(|arg| stack.push_str(arg, &mut data))
         .collect();
-    let padding_null = "\0".repeat(8);
+    let padding_null = "\0".repeat(class.word_size());
     stack.push(padding_null.as_bytes(), &mut data);
 
     stack.push("\0".repeat(stack.get_sp() % 16).as_bytes(), &mut data);
-    assert!(stack.get_sp() % 16 == 0);
+    assert!(stack.get_sp().is_multiple_of(16));
     // Push auxiliary vectors
     for (key, value) in auxv.iter() {
         if (*key) == 25 {
@@ -120,17 +182,42 @@ fn init_stack(args: &[String], envs: &[String], auxv: &BTreeMap<u8, usize>, sp:
 ///
 /// * [`Vec<u8>`] - Initial stack frame of the application
 ///
+/// It fails with [`ElfError::StackTooSmall`] if the frame does not fit into the stack.
+///
 /// # Notes
 ///
 /// The detailed format is described in <https://articles.manugarg.com/aboutelfauxiliaryvectors.html>
+///
+/// The pointers are as wide as the `usize` of the running architecture. Use
+/// [`app_stack_region_for`] to build the stack of an application with another [`ElfClass`].
 pub fn app_stack_region(
     args: &[String],
     envs: &[String],
     auxv: &BTreeMap<u8, usize>,
     stack_base: VirtAddr,
     stack_size: usize,
-) -> Vec<u8> {
+) -> ElfResult<Vec<u8>> {
+    app_stack_region_for(ElfClass::host(), args, envs, auxv, stack_base, stack_size)
+}
+
+/// Generate initial stack frame for user stack of an application with the given [`ElfClass`].
+///
+/// The `argv`/`envp` pointers and the auxiliary vector fields are 4 bytes wide for
+/// [`ElfClass::Elf32`], and 8 bytes wide for [`ElfClass::Elf64`]. The other arguments are the
+/// same as [`app_stack_region`].
+pub fn app_stack_region_for(
+    class: ElfClass,
+    args: &[String],
+    envs: &[String],
+    auxv: &BTreeMap<u8, usize>,
+    stack_base: VirtAddr,
+    stack_size: usize,
+) -> ElfResult<Vec<u8>> {
     let ustack_bottom = stack_base;
     let ustack_top = ustack_bottom + stack_size;
-    init_stack(args, envs, auxv, ustack_top.into())
+    let data = init_stack(class, args, envs, auxv, ustack_top.into());
+    if data.len() > stack_size {
+        return Err(ElfError::StackTooSmall);
+    }
+    Ok(data)
 }
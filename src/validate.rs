@@ -0,0 +1,117 @@
+//! Validation of the `LOAD` segments before they are mapped.
+extern crate alloc;
+use alloc::vec::Vec;
+use core::fmt;
+use xmas_elf::program::Type;
+
+use crate::{image::program_headers, ElfResult, LoadConfig, ProgramHeaderInfo};
+
+/// A problem found in a `LOAD` segment by [`validate_segments`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentIssue {
+    /// `p_offset + p_filesz` is outside the file.
+    OutOfFile,
+    /// `p_filesz` is larger than `p_memsz`.
+    FileSizeExceedsMemSize,
+    /// `base + p_vaddr + p_memsz` overflows.
+    AddressOverflow,
+    /// The segment ends above the limit of the user address space.
+    AboveUserLimit,
+    /// The segment overlaps the `LOAD` segment with the given index.
+    Overlap(usize),
+    /// The segment is both writable and executable, which is denied by the config.
+    WritableExecutable,
+}
+
+/// A problem found in a `LOAD` segment, together with the index of the segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentDiagnostic {
+    /// Index of the segment among the `LOAD` segments, which is also its index in the
+    /// result of [`crate::elf_segments`]
+    pub index: usize,
+    /// The problem found in the segment
+    pub issue: SegmentIssue,
+}
+
+impl fmt::Display for SegmentDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LOAD segment {}: ", self.index)?;
+        match self.issue {
+            SegmentIssue::OutOfFile => write!(f, "data out of the file bounds"),
+            SegmentIssue::FileSizeExceedsMemSize => write!(f, "file size exceeds memory size"),
+            SegmentIssue::AddressOverflow => write!(f, "address overflow"),
+            SegmentIssue::AboveUserLimit => write!(f, "above the user address space"),
+            SegmentIssue::Overlap(other) => write!(f, "overlaps LOAD segment {}", other),
+            SegmentIssue::WritableExecutable => write!(f, "writable and executable"),
+        }
+    }
+}
+
+/// Check the `LOAD` segments of the elf file loaded at `base_addr`.
+///
+/// [`crate::elf_segments`] rejects the file with the first problem found here, so it can be
+/// called beforehand to report every problem of a file.
+///
+/// # Return
+///
+/// All the problems found, which is empty if the segments can be mapped.
+pub fn validate_segments(
+    elf: &xmas_elf::ElfFile,
+    base_addr: usize,
+    config: &LoadConfig,
+) -> ElfResult<Vec<SegmentDiagnostic>> {
+    Ok(validate_from(
+        elf.input.len(),
+        &program_headers(elf)?,
+        base_addr,
+        config,
+    ))
+}
+
+pub(crate) fn validate_from(
+    file_size: usize,
+    program_headers: &[ProgramHeaderInfo],
+    base_addr: usize,
+    config: &LoadConfig,
+) -> Vec<SegmentDiagnostic> {
+    let mut diagnostics = Vec::new();
+    // Memory ranges of the checked segments, which are not overflowed.
+    let mut ranges: Vec<(usize, u64, u64)> = Vec::new();
+    let loads = program_headers.iter().filter(|ph| ph.type_ == Type::Load);
+    for (index, ph) in loads.enumerate() {
+        let mut report = |issue| diagnostics.push(SegmentDiagnostic { index, issue });
+        if ph
+            .offset
+            .checked_add(ph.file_size)
+            .is_none_or(|end| end > file_size as u64)
+        {
+            report(SegmentIssue::OutOfFile);
+        }
+        if ph.file_size > ph.mem_size {
+            report(SegmentIssue::FileSizeExceedsMemSize);
+        }
+        if config.deny_wx() && ph.flags.is_write() && ph.flags.is_execute() {
+            report(SegmentIssue::WritableExecutable);
+        }
+        let Some((start, end)) = ph
+            .vaddr
+            .checked_add(base_addr as u64)
+            .and_then(|start| Some((start, start.checked_add(ph.mem_size)?)))
+            .filter(|&(_, end)| end <= usize::MAX as u64)
+        else {
+            report(SegmentIssue::AddressOverflow);
+            continue;
+        };
+        if config.user_limit().is_some_and(|limit| end > limit as u64) {
+            report(SegmentIssue::AboveUserLimit);
+        }
+        if let Some(&(other, ..)) = ranges
+            .iter()
+            .find(|&&(_, other_start, other_end)| start < other_end && other_start < end)
+        {
+            report(SegmentIssue::Overlap(other));
+        }
+        ranges.push((index, start, end));
+    }
+    diagnostics
+}
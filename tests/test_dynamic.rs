@@ -19,10 +19,615 @@ fn test_elf_parser() {
     let base_addr = kernel_elf_parser::elf_base_addr(&elf, elf_base_addr).unwrap();
     assert_eq!(base_addr, elf_base_addr);
 
-    let segments = kernel_elf_parser::elf_segments(&elf, base_addr);
+    let segments = kernel_elf_parser::elf_segments(&elf, base_addr).unwrap();
     assert_eq!(segments.len(), 4);
     for segment in segments.iter() {
         println!("{:?} {:?}", segment.vaddr, segment.flags);
     }
     assert_eq!(segments[0].vaddr, VirtAddr::from_usize(0x1000));
 }
+
+#[test]
+fn test_relocate_dispatch() {
+    use kernel_elf_parser::arch::{relocate_pairs, relocate_pairs_for, Machine};
+    use kernel_elf_parser::ElfError;
+    let elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    assert_eq!(Machine::from_elf(&elf), Ok(Machine::X86_64));
+    // The symbols imported from libc can not be resolved without the dynamic linker.
+    assert_eq!(
+        relocate_pairs(&elf, 0x1000).err(),
+        Some(ElfError::SymbolNotFound("__libc_start_main".into()))
+    );
+    assert_eq!(
+        relocate_pairs_for(Machine::AArch64, &elf, 0x1000).err(),
+        Some(ElfError::BadMachine(0x3e))
+    );
+}
+
+#[test]
+fn test_interp() {
+    use kernel_elf_parser::{ElfError, ElfImage, LinkKind};
+    let elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let image = ElfImage::parse(&elf_bytes).unwrap();
+    assert_eq!(image.interp(), Ok(Some("/lib64/ld-linux-x86-64.so.2")));
+    assert_eq!(image.link_kind(), Ok(LinkKind::Dynamic));
+
+    let static_bytes = include_bytes!("elf_static").to_vec();
+    let elf = xmas_elf::ElfFile::new(static_bytes.as_slice()).unwrap();
+    assert_eq!(kernel_elf_parser::elf_interp(&elf), Ok(None));
+    assert_eq!(kernel_elf_parser::elf_link_kind(&elf), Ok(LinkKind::Static));
+
+    // Drop the NUL terminator of the path.
+    let mut bad_bytes = elf_bytes.clone();
+    bad_bytes[0x318 + 0x1b] = b'!';
+    let elf = xmas_elf::ElfFile::new(bad_bytes.as_slice()).unwrap();
+    assert_eq!(
+        kernel_elf_parser::elf_interp(&elf),
+        Err(ElfError::BadInterpreter)
+    );
+}
+
+#[test]
+fn test_exec_plan() {
+    use kernel_elf_parser::{ElfImage, ExecPlan};
+    const AT_PHDR: u8 = 3;
+    const AT_BASE: u8 = 7;
+    const AT_ENTRY: u8 = 9;
+    let elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let main = ElfImage::parse(&elf_bytes).unwrap();
+    // Any position-independent file can stand in for the dynamic linker.
+    let interp = ElfImage::parse(&elf_bytes).unwrap();
+    let entry = main.elf().header.pt2.entry_point() as usize;
+
+    let plan = ExecPlan::builder(&main, 0x1000)
+        .interpreter(&interp, 0x10_0000)
+        .build()
+        .unwrap();
+    assert_eq!(plan.segments.len(), 8);
+    assert_eq!(plan.entry.as_usize(), 0x10_0000 + entry);
+    assert_eq!(plan.main_entry.as_usize(), 0x1000 + entry);
+    assert_eq!(plan.auxv[&AT_BASE], 0x10_0000);
+    assert_eq!(plan.auxv[&AT_ENTRY], 0x1000 + entry);
+    assert_eq!(plan.auxv[&AT_PHDR], 0x1040);
+    // The program break follows the main program instead of the interpreter.
+    assert_eq!(plan.brk_start, 0x6000);
+
+    let plan = ExecPlan::builder(&main, 0x1000)
+        .brk_offset(0x3000)
+        .build()
+        .unwrap();
+    assert_eq!(plan.brk_start, 0x9000);
+    assert_eq!(plan.segments.len(), 4);
+    assert_eq!(plan.entry, plan.main_entry);
+    assert_eq!(plan.auxv[&AT_BASE], 0);
+}
+
+#[test]
+fn test_relro() {
+    use kernel_elf_parser::ElfImage;
+    use memory_addr::VirtAddr;
+    let elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let image = ElfImage::parse(&elf_bytes).unwrap();
+    // PT_GNU_RELRO covers 0x3db8..0x4000 before adding the base address.
+    assert_eq!(
+        image.relro(0x1000).unwrap(),
+        Some(VirtAddr::from(0x4000)..VirtAddr::from(0x5000))
+    );
+    let elf = image.elf();
+    assert_eq!(
+        kernel_elf_parser::elf_relro(elf, 0).unwrap(),
+        Some(VirtAddr::from(0x3000)..VirtAddr::from(0x4000))
+    );
+}
+
+#[test]
+fn test_aslr_base() {
+    use kernel_elf_parser::{
+        AslrPolicy, AslrRange, BaseKind, ElfError, EntropySource, ADDR_NO_RANDOMIZE,
+    };
+    struct Fixed(u64);
+    impl EntropySource for Fixed {
+        fn next_u64(&mut self) -> u64 {
+            self.0
+        }
+    }
+    let range = |window, entropy_bits| AslrRange {
+        window,
+        entropy_bits,
+    };
+    let policy = AslrPolicy::new(
+        range(0x5555_0000_0000..0x5655_0000_0000, 8),
+        range(0x7f00_0000_0000..0x7fff_0000_0000, 8),
+        range(0x7f00_0000_0000..0x7fff_0000_0000, 8),
+    );
+    let mut entropy = Fixed(0x1234);
+    // Only the low 8 bits of the random number are used.
+    assert_eq!(
+        policy.pick_base(BaseKind::Executable, 0x5000, 0x1000, &mut entropy),
+        Ok(0x5555_0003_4000)
+    );
+    assert_eq!(
+        policy.pick_base(BaseKind::Interpreter, 0x5000, 0x1000, &mut entropy),
+        Ok(0x7fff_0000_0000 - 0x5000 - 0x3_4000)
+    );
+    assert_eq!(
+        policy.pick_base(BaseKind::Executable, 0x200_0000_0000, 0x1000, &mut entropy),
+        Err(ElfError::NoAddressSpace)
+    );
+
+    let policy = policy.with_brk_range(0x200_0000);
+    assert_eq!(policy.brk_offset(0x1000, &mut entropy), 0x123_4000);
+
+    let policy = policy.with_personality(ADDR_NO_RANDOMIZE);
+    assert_eq!(policy.brk_offset(0x1000, &mut entropy), 0);
+    assert_eq!(
+        policy.pick_base(BaseKind::Executable, 0x5000, 0x20_0000, &mut entropy),
+        Ok(0x5555_0000_0000)
+    );
+    assert_eq!(
+        policy.pick_base(BaseKind::MmapBase, 0, 0x1000, &mut entropy),
+        Ok(0x7fff_0000_0000)
+    );
+}
+
+#[test]
+fn test_layout() {
+    use kernel_elf_parser::{ElfError, ElfImage, ElfLayout};
+    let elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let image = ElfImage::parse(&elf_bytes).unwrap();
+    let layout = image.layout().unwrap();
+    assert_eq!(
+        layout,
+        ElfLayout {
+            min_vaddr: 0,
+            max_vaddr: 0x5000,
+            max_align: 0x1000,
+        }
+    );
+    assert_eq!(layout.span(), 0x5000);
+    assert_eq!(image.base_addr(0x1000), Ok(0x1000));
+
+    // A shared object linked at 0x10000 is moved down by the bias.
+    let layout = ElfLayout {
+        min_vaddr: 0x10000,
+        ..layout
+    };
+    assert_eq!(layout.load_bias(0x20000), Ok(0x10000));
+    assert_eq!(layout.load_bias(0x1000), Err(ElfError::AddressOverflow));
+}
+
+#[test]
+fn test_notes() {
+    use kernel_elf_parser::note::{
+        AbiTag, GNU_PROPERTY_X86_FEATURE_1_IBT, GNU_PROPERTY_X86_FEATURE_1_SHSTK,
+    };
+    use kernel_elf_parser::{ElfError, ElfImage, ExecPlan, GnuProperties};
+    let elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let image = ElfImage::parse(&elf_bytes).unwrap();
+    let notes = image.notes().unwrap();
+    assert_eq!(notes.len(), 3);
+    let build_id = notes.iter().find_map(|note| note.build_id()).unwrap();
+    assert_eq!(
+        build_id,
+        [
+            0xf1, 0x4b, 0xf2, 0xe1, 0x5c, 0xab, 0xc1, 0x79, 0xd8, 0x2a, 0x09, 0xa2, 0xde, 0x5b,
+            0xf1, 0x5d, 0xa6, 0xe5, 0xb7, 0x5c
+        ]
+    );
+    assert_eq!(
+        notes.iter().find_map(|note| note.abi_tag()),
+        Some(AbiTag {
+            os: 0,
+            major: 3,
+            minor: 2,
+            patch: 0,
+        })
+    );
+    assert_eq!(
+        image.gnu_properties().unwrap(),
+        GnuProperties {
+            x86_features: GNU_PROPERTY_X86_FEATURE_1_IBT | GNU_PROPERTY_X86_FEATURE_1_SHSTK,
+            x86_isa_needed: 1,
+            aarch64_features: 0,
+        }
+    );
+
+    // Replace the ISA property by two GNU_PROPERTY_NO_COPY_ON_PROTECTED, which have no data.
+    let mut no_copy_bytes = elf_bytes.clone();
+    for offset in [0x358, 0x360] {
+        no_copy_bytes[offset..offset + 8].copy_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0]);
+    }
+    let image = ElfImage::parse(&no_copy_bytes).unwrap();
+    assert_eq!(
+        image.gnu_properties().unwrap(),
+        GnuProperties {
+            x86_features: GNU_PROPERTY_X86_FEATURE_1_IBT | GNU_PROPERTY_X86_FEATURE_1_SHSTK,
+            x86_isa_needed: 0,
+            aarch64_features: 0,
+        }
+    );
+    assert!(ExecPlan::builder(&image, 0x1000).build().is_ok());
+
+    // A known property must have 4 bytes of data.
+    let mut bad_bytes = elf_bytes.clone();
+    bad_bytes[0x34c] = 8;
+    let image = ElfImage::parse(&bad_bytes).unwrap();
+    assert_eq!(
+        image.gnu_properties(),
+        Err(ElfError::MalformedSection("GNU property note"))
+    );
+
+    // Without PT_GNU_PROPERTY, a broken PT_NOTE does not matter at exec time.
+    let mut legacy_bytes = elf_bytes.clone();
+    legacy_bytes[0x238..0x23c].copy_from_slice(&0u32.to_le_bytes());
+    legacy_bytes[0x368..0x36c].copy_from_slice(&0x1000u32.to_le_bytes());
+    let image = ElfImage::parse(&legacy_bytes).unwrap();
+    assert!(image.notes().is_err());
+    assert_eq!(image.gnu_properties().unwrap(), GnuProperties::default());
+    assert!(ExecPlan::builder(&image, 0x1000).build().is_ok());
+}
+
+#[cfg(feature = "x86_64")]
+#[test]
+fn test_cet_features() {
+    use kernel_elf_parser::arch::x86_64::{shadow_stack_size, CetFeatures};
+    use kernel_elf_parser::{ElfImage, ExecPlan};
+    let elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let main = ElfImage::parse(&elf_bytes).unwrap();
+    let plan = ExecPlan::builder(&main, 0x1000).build().unwrap();
+    assert_eq!(
+        CetFeatures::from_plan(&plan),
+        CetFeatures {
+            ibt: true,
+            shstk: true,
+        }
+    );
+
+    // The static file has no GNU property, so CET is disabled for the process.
+    let static_bytes = include_bytes!("elf_static").to_vec();
+    let interp = ElfImage::parse(&static_bytes).unwrap();
+    let plan = ExecPlan::builder(&main, 0x1000)
+        .interpreter(&interp, 0)
+        .build()
+        .unwrap();
+    assert_eq!(CetFeatures::from_plan(&plan), CetFeatures::default());
+
+    assert_eq!(shadow_stack_size(0x80_0001, 0x1000), 0x80_1000);
+    assert_eq!(shadow_stack_size(usize::MAX, 0x1000), 1 << 32);
+}
+
+#[test]
+fn test_stripped_section_headers() {
+    use kernel_elf_parser::arch::relocate_pairs;
+    use kernel_elf_parser::ElfError;
+    let mut elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    // Clear e_shoff, e_shnum and e_shstrndx, so only the dynamic segment locates the
+    // relocations.
+    elf_bytes[0x28..0x30].fill(0);
+    elf_bytes[0x3c..0x40].fill(0);
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    assert_eq!(
+        relocate_pairs(&elf, 0x1000).err(),
+        Some(ElfError::SymbolNotFound("__libc_start_main".into()))
+    );
+}
+
+#[cfg(feature = "x86_64")]
+#[test]
+fn test_relr() {
+    use kernel_elf_parser::arch::relocate_pairs;
+    const DYNAMIC: usize = 0x2dc8;
+    let mut elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let mut set_dyn = |index: usize, tag: u64, value: u64| {
+        let entry = DYNAMIC + index * 16;
+        elf_bytes[entry..entry + 8].copy_from_slice(&tag.to_le_bytes());
+        elf_bytes[entry + 8..entry + 16].copy_from_slice(&value.to_le_bytes());
+    };
+    // Empty DT_PLTRELSZ and DT_RELASZ, and replace DT_DEBUG and DT_RELACOUNT by a
+    // DT_RELR table in place of `.rela.dyn`.
+    set_dyn(14, 0x2, 0);
+    set_dyn(18, 0x8, 0);
+    set_dyn(12, 0x24, 0x550);
+    set_dyn(25, 0x23, 24);
+    // 0x3db8, a bitmap of the following word 0x3dc0, and 0x4008.
+    for (i, entry) in [0x3db8u64, 0b11, 0x4008].into_iter().enumerate() {
+        elf_bytes[0x550 + i * 8..0x558 + i * 8].copy_from_slice(&entry.to_le_bytes());
+    }
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    let pairs: Vec<_> = relocate_pairs(&elf, 0x1000)
+        .unwrap()
+        .iter()
+        .map(|pair| (pair.src.as_usize(), pair.dst.as_usize(), pair.count))
+        .collect();
+    assert_eq!(
+        pairs,
+        [
+            (0x2140, 0x4db8, 8),
+            (0x2100, 0x4dc0, 8),
+            (0x5008, 0x5008, 8)
+        ]
+    );
+}
+
+#[cfg(feature = "x86_64")]
+#[test]
+fn test_symbol_resolver() {
+    use kernel_elf_parser::arch::{relocate_pairs_with, NoResolver};
+    use kernel_elf_parser::ElfError;
+    let elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    let libc = |name: &str| match name {
+        "__libc_start_main" => Some(0x7000_0000),
+        "puts" => Some(0x7000_1000),
+        _ => None,
+    };
+    let pairs: Vec<_> = relocate_pairs_with(&elf, 0x1000, &libc)
+        .unwrap()
+        .iter()
+        .map(|pair| (pair.dst.as_usize(), pair.src.as_usize()))
+        .collect();
+    // The weak symbols which are not found are resolved to 0.
+    for (dst, src) in [
+        (0x4fd8, 0x7000_0000),
+        (0x4fe0, 0),
+        (0x4fe8, 0),
+        (0x4ff0, 0),
+        (0x4fd0, 0x7000_1000),
+    ] {
+        assert!(pairs.contains(&(dst, src)), "{dst:#x} -> {src:#x}");
+    }
+
+    let only_libc_start_main = |name: &str| (name == "__libc_start_main").then_some(0x7000_0000);
+    assert_eq!(
+        relocate_pairs_with(&elf, 0x1000, &only_libc_start_main).err(),
+        Some(ElfError::SymbolNotFound("puts".into()))
+    );
+    assert_eq!(
+        relocate_pairs_with(&elf, 0x1000, &NoResolver).err(),
+        Some(ElfError::SymbolNotFound("__libc_start_main".into()))
+    );
+}
+
+#[cfg(feature = "x86_64")]
+#[test]
+fn test_apply_relocations() {
+    use kernel_elf_parser::arch::{apply_relocations_with, RelocTarget};
+    use kernel_elf_parser::{ElfError, ElfResult};
+    use memory_addr::VirtAddr;
+
+    /// The image loaded into a host buffer.
+    struct Memory {
+        base: usize,
+        data: Vec<u8>,
+    }
+
+    impl RelocTarget for Memory {
+        fn write(&mut self, addr: VirtAddr, bytes: &[u8]) -> ElfResult {
+            let start = addr.as_usize() - self.base;
+            self.data[start..start + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    impl Memory {
+        fn load(elf: &xmas_elf::ElfFile, base: usize) -> Self {
+            let segments = kernel_elf_parser::elf_segments(elf, base).unwrap();
+            let end = segments
+                .last()
+                .map(|s| s.vaddr.as_usize() + s.size)
+                .unwrap();
+            let mut data = vec![0; end - base];
+            for segment in segments {
+                let start = segment.vaddr.as_usize() - base;
+                let file_data = segment.data.unwrap();
+                data[start..start + file_data.len()].copy_from_slice(file_data);
+            }
+            Self { base, data }
+        }
+
+        fn read(&self, addr: usize) -> u64 {
+            let start = addr - self.base;
+            u64::from_le_bytes(self.data[start..start + 8].try_into().unwrap())
+        }
+    }
+
+    let libc = |name: &str| match name {
+        "__libc_start_main" => Some(0x7000_0000),
+        "puts" => Some(0x7000_1000),
+        _ => None,
+    };
+    let base = 0x10_0000;
+    let mut elf_bytes = include_bytes!("elf_dynamic").to_vec();
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    let mut memory = Memory::load(&elf, base);
+    apply_relocations_with(&elf, base, &libc, &mut memory).unwrap();
+    assert_eq!(memory.read(base + 0x3db8), base as u64 + 0x1140);
+    assert_eq!(memory.read(base + 0x4008), base as u64 + 0x4008);
+    assert_eq!(memory.read(base + 0x3fd8), 0x7000_0000);
+    assert_eq!(memory.read(base + 0x3fd0), 0x7000_1000);
+    assert_eq!(memory.read(base + 0x3fe8), 0);
+
+    // Move the first relocation of `.rela.dyn` into the text segment.
+    elf_bytes[0x550..0x558].copy_from_slice(&0x1000u64.to_le_bytes());
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    let mut memory = Memory::load(&elf, base);
+    let text = memory.read(base + 0x1000);
+    assert_eq!(
+        apply_relocations_with(&elf, base, &libc, &mut memory),
+        Err(ElfError::BadSegment(
+            "relocation outside the writable segments"
+        ))
+    );
+    assert_eq!(memory.read(base + 0x1000), text);
+    assert_eq!(memory.read(base + 0x3dc0), 0x1100);
+
+    // The tables of big-endian files are not read.
+    let mut be_bytes = include_bytes!("elf_dynamic").to_vec();
+    be_bytes[5] = 2;
+    let elf = xmas_elf::ElfFile::new(be_bytes.as_slice()).expect("Failed to read elf file");
+    assert_eq!(
+        apply_relocations_with(&elf, base, &libc, &mut memory),
+        Err(ElfError::BadEncoding)
+    );
+}
+
+/// The relocate pairs of the elf file as `(dst, src, count)`.
+#[allow(dead_code)]
+fn relocations_of(
+    elf: &xmas_elf::ElfFile,
+    base: usize,
+    resolver: &dyn kernel_elf_parser::arch::SymbolResolver,
+) -> Vec<(usize, usize, usize)> {
+    kernel_elf_parser::arch::relocate_pairs_with(elf, base, resolver)
+        .unwrap()
+        .iter()
+        .map(|pair| (pair.dst.as_usize(), pair.src.as_usize(), pair.count))
+        .collect()
+}
+
+/// Resolves the symbols of the `libext.so` the fixtures are linked against.
+#[allow(dead_code)]
+fn libext(name: &str) -> Option<usize> {
+    match name {
+        "ext_data" => Some(0x7000_0000),
+        "ext_func" => Some(0x7000_1000),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "x86")]
+#[test]
+fn test_relocate_i386() {
+    use kernel_elf_parser::arch::{relocate_pairs, Machine};
+    use kernel_elf_parser::ElfError;
+    // A PIE assembled by llvm-mc and linked by lld against a `libext.so`.
+    let elf_bytes = include_bytes!("elf_dynamic_i386").to_vec();
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    assert_eq!(Machine::from_elf(&elf), Ok(Machine::X86));
+    // The first symbol relocation of `.rel.dyn` refers to `ext_data`.
+    assert_eq!(
+        relocate_pairs(&elf, 0x10_0000).err(),
+        Some(ElfError::SymbolNotFound("ext_data".into()))
+    );
+    let pairs = relocations_of(&elf, 0x10_0000, &libext);
+    assert_eq!(pairs.len(), 6);
+    for pair in [
+        // R_386_RELATIVE, whose addend `_start + 4` is stored in the relocated field
+        (0x10_21e0, 0x10_11a8, 4),
+        // R_386_32 of `ext_data + 8` and R_386_GLOB_DAT of `ext_data`
+        (0x10_21e4, 0x7000_0008, 4),
+        (0x10_2274, 0x7000_0000, 4),
+        // The weak undefined `weak_sym` is NULL.
+        (0x10_21e8, 0, 4),
+        (0x10_2278, 0, 4),
+        // R_386_JUMP_SLOT of `ext_func`
+        (0x10_2288, 0x7000_1000, 4),
+    ] {
+        assert!(pairs.contains(&pair), "{pair:x?}");
+    }
+}
+
+#[cfg(feature = "arm")]
+#[test]
+fn test_relocate_arm() {
+    use kernel_elf_parser::arch::{relocate_pairs, Machine};
+    use kernel_elf_parser::ElfError;
+    // A shared object assembled by llvm-mc and linked by lld against a `libext.so`, so its
+    // own symbols are relocated by name.
+    let elf_bytes = include_bytes!("elf_shared_arm").to_vec();
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    assert_eq!(Machine::from_elf(&elf), Ok(Machine::Arm));
+    assert_eq!(
+        relocate_pairs(&elf, 0x100_0000).err(),
+        Some(ElfError::SymbolNotFound("ext_data".into()))
+    );
+    let pairs = relocations_of(&elf, 0x100_0000, &libext);
+    assert_eq!(pairs.len(), 9);
+    for pair in [
+        // R_ARM_RELATIVE of `abs_ref + 2`
+        (0x102_025c, 0x102_0256, 4),
+        // R_ARM_ABS32 of `entry + 4` and `shared_data + 12`, whose addends are stored in the
+        // relocated fields
+        (0x102_0250, 0x101_0204, 4),
+        (0x102_0260, 0x102_025c, 4),
+        // R_ARM_GLOB_DAT of `shared_data`
+        (0x102_02ec, 0x102_0250, 4),
+        // R_ARM_ABS32 of `ext_data + 8` and R_ARM_GLOB_DAT of `ext_data`
+        (0x102_0254, 0x7000_0008, 4),
+        (0x102_02e4, 0x7000_0000, 4),
+        // The weak undefined `weak_sym` is NULL.
+        (0x102_0258, 0, 4),
+        (0x102_02e8, 0, 4),
+        // R_ARM_JUMP_SLOT of `ext_func`
+        (0x102_02fc, 0x7000_1000, 4),
+    ] {
+        assert!(pairs.contains(&pair), "{pair:x?}");
+    }
+
+    // Make `entry` an absolute symbol (SHN_ABS) of value 0x1234, which is not moved by the base.
+    let mut abs_bytes = elf_bytes.clone();
+    abs_bytes[0x138..0x13c].copy_from_slice(&0x1234u32.to_le_bytes());
+    abs_bytes[0x142..0x144].copy_from_slice(&0xfff1u16.to_le_bytes());
+    let elf = xmas_elf::ElfFile::new(abs_bytes.as_slice()).expect("Failed to read elf file");
+    let pairs = relocations_of(&elf, 0x100_0000, &libext);
+    assert!(pairs.contains(&(0x102_0250, 0x1238, 4)), "{pairs:x?}");
+}
+
+#[cfg(feature = "riscv")]
+#[test]
+fn test_relocate_riscv64() {
+    use kernel_elf_parser::arch::Machine;
+    // A PIE assembled by llvm-mc and linked by lld against a `libext.so`.
+    let elf_bytes = include_bytes!("elf_dynamic_riscv64").to_vec();
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    assert_eq!(Machine::from_elf(&elf), Ok(Machine::RiscV));
+    let mut pairs = relocations_of(&elf, 0x10_0000, &libext);
+    pairs.sort();
+    assert_eq!(
+        pairs,
+        [
+            // R_RISCV_RELATIVE of `_start + 4`
+            (0x10_2370, 0x10_1304, 8),
+            // R_RISCV_64 of `ext_data + 8` and `weak_sym + 16`
+            (0x10_2378, 0x7000_0008, 8),
+            (0x10_2380, 0x10, 8),
+            // The GOT entries of `ext_data` and the weak undefined `weak_sym`
+            (0x10_24a0, 0x7000_0000, 8),
+            (0x10_24a8, 0, 8),
+            // R_RISCV_JUMP_SLOT of `ext_func` and the weak undefined `weak_func`
+            (0x10_24c0, 0x7000_1000, 8),
+            (0x10_24c8, 0, 8),
+        ]
+    );
+}
+
+#[cfg(feature = "aarch64")]
+#[test]
+fn test_relocate_aarch64() {
+    use kernel_elf_parser::arch::{relocate_pairs, Machine};
+    use kernel_elf_parser::ElfError;
+    // A PIE assembled by llvm-mc and linked by lld against a `libext.so`.
+    let elf_bytes = include_bytes!("elf_dynamic_aarch64").to_vec();
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).expect("Failed to read elf file");
+    assert_eq!(Machine::from_elf(&elf), Ok(Machine::AArch64));
+    assert_eq!(
+        relocate_pairs(&elf, 0x100_0000).err(),
+        Some(ElfError::SymbolNotFound("ext_data".into()))
+    );
+    let mut pairs = relocations_of(&elf, 0x100_0000, &libext);
+    pairs.sort();
+    assert_eq!(
+        pairs,
+        [
+            // R_AARCH64_RELATIVE of `_start + 4`
+            (0x102_0330, 0x101_02d4, 8),
+            // R_AARCH64_GLOB_DAT of `ext_data` and the weak undefined `weak_sym`
+            (0x102_0448, 0x7000_0000, 8),
+            (0x102_0450, 0, 8),
+            // R_AARCH64_JUMP_SLOT of `ext_func` and the weak undefined `weak_func`
+            (0x102_0470, 0x7000_1000, 8),
+            (0x102_0478, 0, 8),
+        ]
+    );
+}
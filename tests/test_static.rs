@@ -20,7 +20,7 @@ fn test_elf_parser() {
     let base_addr = kernel_elf_parser::elf_base_addr(&elf, elf_base_addr).unwrap();
     assert_eq!(base_addr, 0);
 
-    let segments = kernel_elf_parser::elf_segments(&elf, base_addr);
+    let segments = kernel_elf_parser::elf_segments(&elf, base_addr).unwrap();
     assert_eq!(segments.len(), 4);
     let mut last_start = VirtAddr::from_usize(0);
     for segment in segments.iter() {
@@ -34,7 +34,7 @@ fn test_elf_parser() {
 }
 
 fn test_ustack(elf: &xmas_elf::ElfFile, base_addr: usize) {
-    let auxv = kernel_elf_parser::auxv_vector(&elf, base_addr);
+    let auxv = kernel_elf_parser::auxv_vector(elf, base_addr).unwrap();
     const AT_PHENT: u8 = 4;
     let phent = auxv.get(&AT_PHENT).unwrap();
     assert_eq!(*phent, 56);
@@ -48,7 +48,314 @@ fn test_ustack(elf: &xmas_elf::ElfFile, base_addr: usize) {
     let ustack_bottom = ustack_end - ustack_size;
 
     let stack_data =
-        kernel_elf_parser::app_stack_region(&args, &envs, &auxv, ustack_bottom.into(), ustack_size);
+        kernel_elf_parser::app_stack_region(&args, &envs, &auxv, ustack_bottom.into(), ustack_size)
+            .unwrap();
     // The first 8 bytes of the stack is the number of arguments.
     assert_eq!(stack_data[0..8], [3, 0, 0, 0, 0, 0, 0, 0]);
 }
+
+#[test]
+fn test_malformed_elf() {
+    use kernel_elf_parser::ElfError;
+    let elf_bytes = include_bytes!("elf_static");
+    let mut aligned_elf_bytes = elf_bytes.to_vec();
+    // Move the program header table out of the file.
+    aligned_elf_bytes[0x20..0x28].copy_from_slice(&u64::MAX.to_le_bytes());
+    let elf =
+        xmas_elf::ElfFile::new(aligned_elf_bytes.as_slice()).expect("Failed to read elf file");
+    let err = kernel_elf_parser::elf_segments(&elf, 0).err().unwrap();
+    assert_eq!(err, ElfError::OutOfBounds);
+    assert_eq!(axerrno::LinuxError::from(err), axerrno::LinuxError::ENOEXEC);
+}
+
+#[test]
+fn test_elf_image() {
+    use kernel_elf_parser::{ElfError, ElfImage, SegmentSharing};
+    let elf_bytes = include_bytes!("elf_static").to_vec();
+    let image = ElfImage::parse(&elf_bytes).unwrap();
+    assert_eq!(image.base_addr(0x1000).unwrap(), 0);
+    // The .bss of the data segment ends at 0x4057d8.
+    assert_eq!(image.brk_start(0).unwrap(), 0x406000);
+    let segments = image.segments(0).unwrap();
+    assert_eq!(segments.len(), 4);
+    // The data segment ends with .bss inside its last file page.
+    let data = &segments[3];
+    assert_eq!(data.vaddr, memory_addr::VirtAddr::from(0x404000));
+    assert_eq!(data.offset, 0x3000);
+    assert_eq!(data.file_size, 0x1130);
+    assert_eq!(data.zero_size, 0xed0);
+    assert_eq!(data.anon_size, 0);
+    assert_eq!(segments[0].zero_size, 0);
+    assert_eq!(data.file_pages(), 0x3000..0x5000);
+    assert_eq!(data.sharing(), SegmentSharing::PrivateCow);
+    assert_eq!(segments[1].sharing(), SegmentSharing::SharedReadOnly);
+    let file_segments = image.file_segments(0).unwrap();
+    assert!(file_segments.iter().all(|segment| segment.data.is_none()));
+    assert_eq!(file_segments[3].file_pages(), data.file_pages());
+    // The data is borrowed from the file instead of being copied.
+    assert_eq!(data.data.unwrap().as_ptr(), elf_bytes[0x3000..].as_ptr());
+
+    // Without file data, the unaligned data segment is only anonymous pages.
+    let mut bss_bytes = elf_bytes.clone();
+    bss_bytes[0x40 + 3 * 0x38 + 0x20..][..8].fill(0);
+    let bss_image = ElfImage::parse(&bss_bytes).unwrap();
+    let bss_segments = bss_image.segments(0).unwrap();
+    let bss = &bss_segments[3];
+    assert_eq!(bss.vaddr, memory_addr::VirtAddr::from(0x404000));
+    assert_eq!(
+        (bss.file_size, bss.zero_size, bss.anon_size),
+        (0, 0, 0x2000)
+    );
+    assert_eq!(bss.data, Some(&[][..]));
+    assert!(bss.file_pages().is_empty());
+    assert_eq!(
+        image.entry(0).unwrap(),
+        memory_addr::VirtAddr::from(0x40102f)
+    );
+
+    // Change `e_machine` to aarch64.
+    let mut bad_bytes = elf_bytes.clone();
+    bad_bytes[18..20].copy_from_slice(&0xb7u16.to_le_bytes());
+    assert_eq!(
+        ElfImage::parse(&bad_bytes).err(),
+        Some(ElfError::BadMachine(0xb7))
+    );
+}
+
+#[test]
+fn test_ustack_elf32() {
+    use kernel_elf_parser::ElfClass;
+    let args: Vec<String> = vec!["arg1".to_string(), "arg2".to_string()];
+    let envs: Vec<String> = vec!["LOG=file".to_string()];
+    let mut auxv = std::collections::BTreeMap::new();
+    const AT_PAGESZ: u8 = 6;
+    auxv.insert(AT_PAGESZ, 0x1000);
+
+    let ustack_end = 0x4000_0000;
+    let ustack_size = 0x2_0000;
+    let stack_data = kernel_elf_parser::app_stack_region_for(
+        ElfClass::Elf32,
+        &args,
+        &envs,
+        &auxv,
+        (ustack_end - ustack_size).into(),
+        ustack_size,
+    )
+    .unwrap();
+    let word =
+        |index: usize| u32::from_le_bytes(stack_data[index * 4..index * 4 + 4].try_into().unwrap());
+    // argc, argv[0], argv[1], NULL, envp[0], NULL, AT_PAGESZ
+    assert_eq!(word(0), 2);
+    assert_eq!(word(3), 0);
+    assert_eq!(word(5), 0);
+    assert_eq!(word(6), AT_PAGESZ as u32);
+    assert_eq!(word(7), 0x1000);
+}
+
+#[test]
+fn test_page_size() {
+    use kernel_elf_parser::{ElfError, ElfImage, LoadConfig};
+    const AT_PAGESZ: u8 = 6;
+    let elf_bytes = include_bytes!("elf_static").to_vec();
+    let image = ElfImage::parse(&elf_bytes).unwrap();
+    assert_eq!(image.auxv(0).unwrap()[&AT_PAGESZ], 0x1000);
+
+    // The segments are aligned to 4K, so they can not be mapped with 64K pages.
+    let image = image.with_config(LoadConfig::new().with_page_size(0x1_0000));
+    assert_eq!(image.auxv(0).unwrap()[&AT_PAGESZ], 0x1_0000);
+    assert_eq!(
+        image.segments(0).err(),
+        Some(ElfError::BadSegment(
+            "alignment is smaller than the page size"
+        ))
+    );
+}
+
+#[test]
+fn test_stack_info() {
+    use kernel_elf_parser::ElfImage;
+    use page_table_entry::MappingFlags;
+    let elf_bytes = include_bytes!("elf_static").to_vec();
+    let image = ElfImage::parse(&elf_bytes).unwrap();
+    let stack = image.stack_info();
+    assert_eq!(
+        stack.flags,
+        MappingFlags::USER | MappingFlags::READ | MappingFlags::WRITE
+    );
+    assert_eq!(stack.size_hint, None);
+    assert!(!stack.legacy);
+
+    // Turn PT_GNU_STACK into PT_NULL to get a legacy file.
+    let mut legacy_bytes = elf_bytes.clone();
+    let phoff = image.elf().header.pt2.ph_offset() as usize;
+    let index = image
+        .elf()
+        .program_iter()
+        .position(|ph| ph.get_type() == Ok(xmas_elf::program::Type::OsSpecific(0x6474_e551)))
+        .unwrap();
+    legacy_bytes[phoff + index * 56..phoff + index * 56 + 4].fill(0);
+    let stack = kernel_elf_parser::elf_stack_info(
+        &xmas_elf::ElfFile::new(legacy_bytes.as_slice()).unwrap(),
+    )
+    .unwrap();
+    assert!(stack.legacy);
+    assert_eq!(
+        stack.flags,
+        MappingFlags::USER | MappingFlags::READ | MappingFlags::WRITE
+    );
+}
+
+#[test]
+fn test_tls_block() {
+    use kernel_elf_parser::{arch::Machine, ElfError, ElfImage, TlsTemplate};
+    let elf_bytes = include_bytes!("elf_static").to_vec();
+    let image = ElfImage::parse(&elf_bytes).unwrap();
+    assert!(image.tls_template().unwrap().is_none());
+
+    let template = TlsTemplate {
+        init: &[1, 2, 3, 4],
+        mem_size: 12,
+        align: 16,
+    };
+    // Variant II: the TLS block ends at the thread pointer, which points to itself.
+    let block = template.build(Machine::X86_64, 0x1000).unwrap();
+    assert_eq!(block.thread_pointer, 0x1010);
+    assert_eq!(block.data.len(), 0x18);
+    assert_eq!(block.data[..4], [1, 2, 3, 4]);
+    assert_eq!(block.data[0x10..], 0x1010usize.to_le_bytes());
+
+    // Variant I: the TLS block follows the 16-byte TCB at the thread pointer.
+    let block = template.build(Machine::AArch64, 0x1008).unwrap();
+    assert_eq!(block.thread_pointer, 0x1010);
+    assert_eq!(block.data.len(), 0x08 + 0x10 + 12);
+    assert_eq!(block.data[0x18..0x1c], [1, 2, 3, 4]);
+
+    // The TLS block starts at the thread pointer on riscv.
+    let block = template.build(Machine::RiscV, 0x1000).unwrap();
+    assert_eq!(block.thread_pointer, 0x1000);
+    assert_eq!(block.data[..4], [1, 2, 3, 4]);
+
+    // A `.tdata` larger than the block, or a block which can not be allocated.
+    let bad_init = TlsTemplate {
+        mem_size: 2,
+        ..template
+    };
+    assert_eq!(
+        bad_init.build(Machine::X86_64, 0x1000).err(),
+        Some(ElfError::BadTls("file size exceeds memory size"))
+    );
+    let huge = TlsTemplate {
+        mem_size: usize::MAX / 2,
+        ..template
+    };
+    for machine in [Machine::X86_64, Machine::AArch64] {
+        assert_eq!(
+            huge.build(machine, 0x1000).err(),
+            Some(ElfError::BadTls("TLS block is too large"))
+        );
+    }
+}
+
+#[test]
+fn test_phdr_addr() {
+    use kernel_elf_parser::{ElfError, ElfImage};
+    const AT_PHDR: u8 = 3;
+    let elf_bytes = include_bytes!("elf_static").to_vec();
+    let image = ElfImage::parse(&elf_bytes).unwrap();
+    // No PT_PHDR, so the address comes from the LOAD segment at file offset 0.
+    assert_eq!(image.auxv(0).unwrap()[&AT_PHDR], 0x400040);
+
+    // Move the first LOAD segment past the program header table.
+    let mut bad_bytes = elf_bytes.clone();
+    let phoff = image.elf().header.pt2.ph_offset() as usize;
+    let index = image
+        .elf()
+        .program_iter()
+        .position(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
+        .unwrap();
+    let ph = phoff + index * 56;
+    bad_bytes[ph + 8..ph + 16].copy_from_slice(&0x1000u64.to_le_bytes());
+    bad_bytes[ph + 16..ph + 24].copy_from_slice(&0x401000u64.to_le_bytes());
+    let elf = xmas_elf::ElfFile::new(bad_bytes.as_slice()).unwrap();
+    assert_eq!(
+        kernel_elf_parser::auxv_vector(&elf, 0).err(),
+        Some(ElfError::PhdrNotMapped)
+    );
+}
+
+#[test]
+fn test_validate_segments() {
+    use kernel_elf_parser::{ElfError, ElfImage, LoadConfig, SegmentDiagnostic, SegmentIssue};
+    let elf_bytes = include_bytes!("elf_static").to_vec();
+    let config = LoadConfig::new().with_deny_wx(true);
+    let image = ElfImage::parse(&elf_bytes).unwrap().with_config(config);
+    assert!(image.validate(0).is_empty());
+
+    let mut bad_bytes = elf_bytes.clone();
+    let phoff = image.elf().header.pt2.ph_offset() as usize;
+    let loads: Vec<usize> = image
+        .elf()
+        .program_iter()
+        .enumerate()
+        .filter(|(_, ph)| ph.get_type() == Ok(xmas_elf::program::Type::Load))
+        .map(|(index, _)| phoff + index * 56)
+        .collect();
+    // Make the text segment writable.
+    bad_bytes[loads[1] + 4] |= 0x2;
+    // Move the read-only data segment onto the text segment.
+    bad_bytes[loads[2] + 16..loads[2] + 24].copy_from_slice(&0x401000u64.to_le_bytes());
+    // Make the file size of the data segment exceed its memory size.
+    bad_bytes[loads[3] + 32..loads[3] + 40].copy_from_slice(&0x1000u64.to_le_bytes());
+    let elf = xmas_elf::ElfFile::new(bad_bytes.as_slice()).unwrap();
+    let diagnostics = kernel_elf_parser::validate_segments(&elf, 0, &config).unwrap();
+    let issues: Vec<_> = diagnostics.iter().map(|d| (d.index, d.issue)).collect();
+    assert_eq!(
+        issues,
+        [
+            (1, SegmentIssue::WritableExecutable),
+            (2, SegmentIssue::Overlap(1)),
+            (3, SegmentIssue::FileSizeExceedsMemSize),
+        ]
+    );
+    assert_eq!(
+        kernel_elf_parser::elf_segments(&elf, 0).err(),
+        Some(ElfError::InvalidSegment(SegmentDiagnostic {
+            index: 2,
+            issue: SegmentIssue::Overlap(1),
+        }))
+    );
+
+    // The segments must stay below the user address space limit.
+    let config = LoadConfig::new().with_user_limit(0x404000);
+    let image = ElfImage::parse(&elf_bytes).unwrap().with_config(config);
+    assert_eq!(image.validate(0)[0].issue, SegmentIssue::AboveUserLimit);
+}
+
+#[cfg(feature = "aarch64")]
+#[test]
+fn test_guard_segments() {
+    use kernel_elf_parser::arch::aarch64::{guard_segments, BranchProtection};
+    use kernel_elf_parser::GnuProperties;
+    let properties = GnuProperties {
+        aarch64_features: 1,
+        ..Default::default()
+    };
+    let protection = BranchProtection::from_properties(&properties);
+    assert_eq!(
+        protection,
+        BranchProtection {
+            bti: true,
+            pac: false,
+        }
+    );
+
+    let elf_bytes = include_bytes!("elf_static").to_vec();
+    let elf = xmas_elf::ElfFile::new(elf_bytes.as_slice()).unwrap();
+    let mut segments = kernel_elf_parser::elf_segments(&elf, 0).unwrap();
+    guard_segments(&mut segments, BranchProtection::default());
+    assert!(segments.iter().all(|segment| !segment.guarded));
+    // Only the text segment is executable.
+    guard_segments(&mut segments, protection);
+    let guarded: Vec<bool> = segments.iter().map(|segment| segment.guarded).collect();
+    assert_eq!(guarded, [false, true, false, false]);
+}